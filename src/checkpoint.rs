@@ -0,0 +1,190 @@
+//! 扫描进度的检查点，中断后可以用 `--resume` 从上次保存的位置继续扫描
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::result::ScanType;
+
+/// 保存到状态文件中的扫描进度
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub scan_type: ScanType,
+    pub seed: u64,
+    pub shard: (usize, usize),      // 分片的序号（从 0 开始）和分片数
+    pub target_count: u64,          // 分片中的目标数，用于检查目标是否和上次一致
+    pub rounds: u8,                 // `--time` 指定的总轮数
+    pub round: u8,                  // 当前的 `--time` 轮次，等于总轮数时扫描已经完成
+    pub position: u64,              // 本轮中在这之前的目标都已经扫描完成
+    pub completed: Vec<u64>,        // 本轮中在 position 之后已经扫描完成的目标，继续扫描时跳过
+    pub results: serde_json::Value, // 扫描累积的结果
+    #[serde(default)]
+    pub output: serde_json::Value, // 输出格式累积的结果
+}
+
+impl Checkpoint {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    // 先写入临时文件再重命名，写入过程中被中断也不会损坏之前的检查点
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// 跟踪本轮中正在扫描的目标的序号，用于确定检查点的位置
+///
+/// 目标完成的顺序和开始的顺序不同，所以检查点保存最早的未完成目标的序号，
+/// 以及在它之后已经完成的目标的序号，继续扫描时这些目标不会被再扫描一次
+#[derive(Debug, Default)]
+pub struct Position {
+    next: u64, // 下一个目标的序号
+    // 按套接字保存正在扫描的目标，套接字关闭后描述符可能马上被新的目标复用，所以用队列
+    in_flight: HashMap<RawFd, VecDeque<u64>>,
+    // 正在扫描的目标的序号，最小的就是检查点的位置
+    pending: BTreeSet<u64>,
+    // 在检查点的位置之后已经完成的目标的序号
+    completed: BTreeSet<u64>,
+}
+
+impl Position {
+    pub fn new(start: u64, completed: impl IntoIterator<Item = u64>) -> Self {
+        Self {
+            next: start,
+            completed: completed.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    // 下一个目标在中断前已经扫描完成时跳过它
+    pub fn skip_completed(&mut self) -> bool {
+        if !self.completed.contains(&self.next) {
+            return false;
+        }
+        self.next += 1;
+        self.prune();
+        true
+    }
+
+    // 开始扫描下一个目标
+    pub fn push(&mut self, fd: RawFd) {
+        self.in_flight.entry(fd).or_default().push_back(self.next);
+        self.pending.insert(self.next);
+        self.next += 1;
+    }
+
+    // 使用该套接字的最早的目标扫描完成
+    pub fn done(&mut self, fd: RawFd) {
+        let Some(queue) = self.in_flight.get_mut(&fd) else {
+            return;
+        };
+        let index = queue.pop_front();
+        if queue.is_empty() {
+            self.in_flight.remove(&fd);
+        }
+        if let Some(index) = index {
+            self.pending.remove(&index);
+            self.completed.insert(index);
+            self.prune();
+        }
+    }
+
+    // 在这之前的目标都已经扫描完成
+    pub fn get(&self) -> u64 {
+        self.pending.first().copied().unwrap_or(self.next)
+    }
+
+    // 在 get() 之后已经扫描完成的目标
+    pub fn completed(&self) -> Vec<u64> {
+        self.completed.iter().copied().collect()
+    }
+
+    // 去掉已经在检查点位置之前的序号，只保留位置之后的
+    fn prune(&mut self) {
+        let position = self.get();
+        while self.completed.first().map_or(false, |&i| i < position) {
+            self.completed.pop_first();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let mut pos = Position::new(10, []);
+        assert_eq!(pos.get(), 10);
+        pos.push(3);
+        pos.push(4);
+        pos.push(5);
+        pos.done(4);
+        assert_eq!((pos.get(), pos.completed()), (10, vec![11]));
+        pos.done(3);
+        assert_eq!((pos.get(), pos.completed()), (12, vec![]));
+        // 描述符 5 被新的目标复用
+        pos.push(5);
+        pos.done(5);
+        assert_eq!(pos.get(), 13);
+        pos.done(5);
+        assert_eq!((pos.get(), pos.completed()), (14, vec![]));
+    }
+
+    #[test]
+    fn test_position_resume() {
+        // 中断前 10 之后的 11 和 13 已经完成
+        let mut pos = Position::new(10, [11, 13]);
+        assert!(!pos.skip_completed());
+        pos.push(3);
+        assert!(pos.skip_completed());
+        assert!(!pos.skip_completed());
+        pos.push(4);
+        assert_eq!((pos.get(), pos.completed()), (10, vec![11, 13]));
+        pos.done(3);
+        assert_eq!((pos.get(), pos.completed()), (12, vec![13]));
+        assert!(pos.skip_completed());
+        assert_eq!(pos.get(), 12);
+        pos.done(4);
+        assert_eq!((pos.get(), pos.completed()), (14, vec![]));
+        assert!(!pos.skip_completed());
+    }
+
+    #[test]
+    fn test_checkpoint_save_load() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+        let checkpoint = Checkpoint {
+            scan_type: ScanType::TcpConnect,
+            seed: 42,
+            shard: (1, 3),
+            target_count: 1000,
+            rounds: 4,
+            round: 2,
+            position: 123,
+            completed: vec![125, 130],
+            results: serde_json::json!({"10.0.0.1:443": [1, 2]}),
+            output: serde_json::Value::Null,
+        };
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.scan_type, ScanType::TcpConnect);
+        assert_eq!(loaded.seed, 42);
+        assert_eq!(loaded.shard, (1, 3));
+        assert_eq!(loaded.target_count, 1000);
+        assert_eq!((loaded.rounds, loaded.round, loaded.position), (4, 2, 123));
+        assert_eq!(loaded.completed, [125, 130]);
+        assert_eq!(loaded.results, checkpoint.results);
+    }
+}
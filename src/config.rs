@@ -1,30 +1,109 @@
 //! Command line option handling
 
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
 use std::str::FromStr;
 
-use ipnet::Ipv4Net;
+use ipnet::IpNet;
+use rustls::pki_types::ServerName;
+
+use crate::result::ScanType;
 
 /// Command line options
 #[derive(Debug, structopt::StructOpt)]
 #[structopt(version=env!("CARGO_PKG_VERSION"), about="io_uring based network scanner.", long_about=r#"
 Examples:
   - Look for Nginx servers on 192.168.0.1/24:
-    io_uring_scanner 80 192.168.0.1/24 http-header-match --resp-header-regex 'Server: ^nginx'
+    io_uring_scanner -p 80 -i 192.168.0.1/24 --no-default-exclude http-header-match --resp-header-regex 'Server: ^nginx'
+  - Look for Nginx HTTPS servers of example.com on 192.168.0.1/24:
+    io_uring_scanner -p 443 -i 192.168.0.1/24 --no-default-exclude http-header-match --tls --sni example.com --req-host example.com --resp-header-regex 'Server: ^nginx'
   - Look for OpenSSH 8.4 servers on 10.0.0.1/16:
-    io_uring_scanner 22 10.0.0.1/16 ssh-version '^SSH-2\.0-OpenSSH_8\.4'
+    io_uring_scanner -p 22 -i 10.0.0.1/16 --no-default-exclude ssh-version '^SSH-2\.0-OpenSSH_8\.4'
+  - Grab the banners of FTP, SMTP and POP3 servers on 10.0.0.0/24:
+    io_uring_scanner -p 21,25,110 -i 10.0.0.0/24 --no-default-exclude banner --wait-ms 500
+  - Look for Redis servers that answer PING without authentication on 10.0.0.0/24:
+    io_uring_scanner -p 6379 -i 10.0.0.0/24 --no-default-exclude probe --payload 'PING\r\n' '^\+PONG'
+  - Detect services and versions on common ports of 10.0.0.0/24 with the nmap fingerprint database:
+    io_uring_scanner -p 21,22,25,80,3306 -i 10.0.0.0/24 --no-default-exclude service --probes-file /usr/share/nmap/nmap-service-probes
+  - Read the TLS certificates of HTTPS servers on 10.0.0.0/24:
+    io_uring_scanner -p 443 -i 10.0.0.0/24 --no-default-exclude tls --sni example.com --alpn h2,http/1.1 --max-version 1.2
+  - Check which of ports 80, 443 and 8000 to 8100 are open on 10.0.0.0/24:
+    io_uring_scanner -p 80,443,8000-8100 -i 10.0.0.0/24 --no-default-exclude --time 1 tcp-connect
+  - Measure download speed from 104.16.0.1 with a custom Host header:
+    io_uring_scanner -p 80 -i 104.16.0.1/32 --time 1 speed-test --req-host speed.example.com --req-uri /100mb.bin
 "#)]
 pub struct CommandLineOptions {
-    /// TCP port to scan
-    #[structopt(short,long,default_value = "443")]
-    pub port: u16,
+    /// TCP ports to scan, as a comma separated list of ports or port ranges, eg. '80,443,8000-8100'
+    #[structopt(short = "p", long = "port", default_value = "443")]
+    pub ports: PortList,
 
     /// The number of delay times for speedtest. The number of times to delay test a single IP.
     #[structopt(long, default_value = "4")]
     pub time: u8,
 
-    /// IPv4 subnets to scan
+    /// IPv4 or IPv6 subnets to scan
     #[structopt(short,long)]
-    pub ip_subnets: Vec<Ipv4Net>,
+    pub ip_subnets: Vec<IpNet>,
+
+    /// File to read targets from, one per line as a CIDR, an IP, an 'a.b.c.d-e.f.g.h' range or 'ip:port'.
+    /// Open ports of masscan list (-oL) and nmap grepable (-oG) results are also accepted.
+    /// Use '-' to read from stdin
+    #[structopt(long = "target-file", parse(from_os_str))]
+    pub target_files: Vec<std::path::PathBuf>,
+
+    /// IPv4 or IPv6 subnets to exclude from the scan
+    #[structopt(long)]
+    pub exclude: Vec<IpNet>,
+
+    /// File to read subnets to exclude from, in the same format as --target-file
+    #[structopt(long = "exclude-file", parse(from_os_str))]
+    pub exclude_files: Vec<std::path::PathBuf>,
+
+    /// Also scan reserved, private, multicast and broadcast ranges, which are excluded by default
+    #[structopt(long)]
+    pub no_default_exclude: bool,
+
+    /// Seed of the random target order, scans with the same seed and targets use the same order [default: random]
+    #[structopt(long)]
+    pub seed: Option<u64>,
+
+    /// Only scan the i-th of n disjoint parts of the targets, e.g. '2/3'. Run every part with the same --seed
+    /// on different hosts to split a scan between them
+    #[structopt(long, requires = "seed")]
+    pub shard: Option<Shard>,
+
+    /// File to periodically save the scan progress and results to, so the scan can be continued with --resume
+    #[structopt(long, parse(from_os_str))]
+    pub checkpoint: Option<std::path::PathBuf>,
+
+    /// Interval between checkpoints
+    #[structopt(long = "checkpoint-interval-sec", default_value = "60")]
+    pub checkpoint_interval_secs: u64,
+
+    /// Continue a scan from a checkpoint file, using the same targets and options as the interrupted scan.
+    /// New checkpoints are saved to the same file unless --checkpoint is given
+    #[structopt(long, parse(from_os_str))]
+    pub resume: Option<std::path::PathBuf>,
+
+    /// Format of the scan results. csv writes a table with one row per target at the end of the scan,
+    /// aggregating all rounds. xml writes the same structure as nmap -oX, listing hosts with an open port.
+    /// list writes open ports in the masscan -oL format, which can be read back with --target-file.
+    /// binary writes the same records in the masscan -oB format, for masscan --readscan
+    #[structopt(long = "output-format", default_value = "text", possible_values = &OutputFormat::NAMES)]
+    pub output_format: OutputFormat,
+
+    /// Write the scan results to this file instead of stdout
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: Option<std::path::PathBuf>,
+
+    /// Periodically write Prometheus metrics to this file, for the node_exporter textfile collector
+    #[structopt(long, parse(from_os_str))]
+    pub metrics_file: Option<std::path::PathBuf>,
+
+    /// Interval between metrics file updates, in seconds
+    #[structopt(long = "metrics-interval-sec", default_value = "15")]
+    pub metrics_interval_secs: u64,
 
     /// Maximum count of preallocated sockets
     #[structopt(long = "max-prealloc-sockets", default_value = "16")]
@@ -60,18 +139,160 @@ pub struct CommandLineOptions {
     pub scan_opts: ScanOptions,
 }
 
+/// Sorted list of unique ports
+#[derive(Debug, Clone)]
+pub struct PortList(pub Vec<u16>);
+
+impl FromStr for PortList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_port = |p: &str| match p.trim().parse::<u16>() {
+            Ok(0) => Err(format!("Invalid port {:?}: port 0 can not be connected to", p)),
+            Ok(port) => Ok(port),
+            Err(e) => Err(format!("Invalid port {:?}: {}", p, e)),
+        };
+
+        let mut ports = Vec::new();
+        for part in s.split(',') {
+            if let Some((first, last)) = part.split_once('-') {
+                let (first, last) = (parse_port(first)?, parse_port(last)?);
+                if first > last {
+                    return Err(format!("Invalid port range {:?}", part));
+                }
+                ports.extend(first..=last);
+            } else {
+                ports.push(parse_port(part)?);
+            }
+        }
+        ports.sort_unstable();
+        ports.dedup();
+
+        Ok(Self(ports))
+    }
+}
+
+/// Part of the targets to scan, parsed from a 1-based `i/n`
+#[derive(Debug, Clone, Copy)]
+pub struct Shard {
+    pub index: usize, // 从 0 开始
+    pub count: usize,
+}
+
+impl Shard {
+    // 从全部目标中每 count 个取一个，不同分片的目标不重复，合起来正好是全部目标
+    pub fn select<I: Iterator>(&self, iter: I) -> impl Iterator<Item = I::Item> {
+        iter.skip(self.index).step_by(self.count)
+    }
+
+    // 总数为 total 时分片中的目标数
+    pub fn len(&self, total: u64) -> u64 {
+        let (index, count) = (self.index as u64, self.count as u64);
+        total / count + u64::from(index < total % count)
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid shard {:?}, expected i/n with 1 <= i <= n", s);
+        let (index, count) = s.split_once('/').ok_or_else(err)?;
+        let index: usize = index.trim().parse().map_err(|_| err())?;
+        let count: usize = count.trim().parse().map_err(|_| err())?;
+        if index == 0 || index > count {
+            return Err(err());
+        }
+        Ok(Self {
+            index: index - 1,
+            count,
+        })
+    }
+}
+
+/// Format of the scan results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Jsonl,
+    Csv,
+    Xml,
+    List,
+    Binary,
+}
+
+impl OutputFormat {
+    const NAMES: [&'static str; 6] = ["text", "jsonl", "csv", "xml", "list", "binary"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            "xml" => Ok(Self::Xml),
+            "list" => Ok(Self::List),
+            "binary" => Ok(Self::Binary),
+            _ => Err(format!("Invalid output format {:?}", s)),
+        }
+    }
+}
+
 /// Scan specific options
 #[derive(Debug, structopt::StructOpt)]
 pub enum ScanOptions {
+    Banner(BannerScanOptions),
     HttpHeaderMatch(HttpHeaderMatchScanOptions),
+    Probe(ProbeScanOptions),
+    Service(ServiceScanOptions),
+    SpeedTest(SpeedTestScanOptions),
     SshVersion(SshVersionScanOptions),
     TcpConnect(TcpConnectScanOptions),
+    Tls(TlsScanOptions),
+}
+
+impl ScanOptions {
+    pub fn scan_type(&self) -> ScanType {
+        match self {
+            Self::Banner(_) => ScanType::Banner,
+            Self::HttpHeaderMatch(_) => ScanType::HttpHeaderMatch,
+            Self::Probe(_) => ScanType::Probe,
+            Self::Service(_) => ScanType::Service,
+            Self::SpeedTest(_) => ScanType::SpeedTest,
+            Self::SshVersion(_) => ScanType::SshVersion,
+            Self::TcpConnect(_) => ScanType::TcpConnect,
+            Self::Tls(_) => ScanType::Tls,
+        }
+    }
 }
 
 const HTTP_VERBS: [&str; 8] = [
     "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE",
 ];
 
+/// Banner grab scan for any TCP service that speaks first, e.g. FTP, SMTP, POP3 or MySQL
+#[derive(Debug, Clone, structopt::StructOpt)]
+pub struct BannerScanOptions {
+    /// Time to wait for the server to send its banner, in milliseconds [default: --read-timeout-sec]
+    #[structopt(long, parse(try_from_str = parse_wait_ms))]
+    pub wait_ms: Option<u64>,
+
+    /// Regex to match on the banner
+    pub regex: Option<regex::bytes::Regex>,
+}
+
+fn parse_wait_ms(s: &str) -> Result<u64, String> {
+    match s.parse::<u64>() {
+        // 0 的 LinkTimeout 会立即取消读取，不会收到任何 banner
+        Ok(0) => Err("Wait time must be at least 1ms".to_string()),
+        Ok(ms) => Ok(ms),
+        Err(e) => Err(format!("Invalid wait time {:?}: {}", s, e)),
+    }
+}
+
 /// HTTP header match scan
 #[derive(Debug, Clone, structopt::StructOpt)]
 pub struct HttpHeaderMatchScanOptions {
@@ -81,6 +302,18 @@ pub struct HttpHeaderMatchScanOptions {
     #[structopt(long = "req-uri", default_value = "/")]
     pub request_uri: String,
 
+    /// Host header to send, defaults to the target address
+    #[structopt(long = "req-host")]
+    pub request_host: Option<String>,
+
+    /// Connect with TLS, for HTTPS servers. The handshake runs in memory and certificates are not verified
+    #[structopt(long)]
+    pub tls: bool,
+
+    /// Server name to send in the TLS SNI extension, none is sent by default
+    #[structopt(long, requires = "tls", parse(try_from_str = parse_server_name))]
+    pub sni: Option<ServerName<'static>>,
+
     // #[structopt(long = "req-data")]
     // pub request_data: Option<bstr::BString>,
     #[structopt(
@@ -94,6 +327,16 @@ pub struct HttpHeaderMatchScanOptions {
         help = "Regex to match for in response header in 'key: regex' form. Multiple rules will match response if all rules do match."
     )]
     pub response_header_regexs: Vec<ResponseHttpHeaderRegex>,
+
+    #[structopt(
+        long = "capture-header",
+        help = "Response header value to capture and group results by, in 'key: regex' form. The first capture group is used if there is one, otherwise the whole match, eg. 'CF-RAY: -([A-Z]+)$'"
+    )]
+    pub capture_header: Option<ResponseHttpHeaderRegex>,
+}
+
+fn parse_server_name(s: &str) -> Result<ServerName<'static>, String> {
+    ServerName::try_from(s.to_string()).map_err(|e| format!("Invalid server name {:?}: {}", s, e))
 }
 
 #[derive(Debug, Clone)]
@@ -142,6 +385,133 @@ impl FromStr for ResponseHttpHeaderRegex {
     }
 }
 
+impl fmt::Display for ResponseHttpHeaderRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.val_regex)
+    }
+}
+
+/// Send a payload and match the response, for services that wait for the client to speak first, e.g. Redis or memcached
+#[derive(Debug, Clone, structopt::StructOpt)]
+pub struct ProbeScanOptions {
+    /// Payload to send once connected, with \r, \n, \t, \0, \\ and \xNN escapes, eg. 'PING\r\n'
+    #[structopt(long, required_unless = "payload-file", conflicts_with = "payload-file")]
+    pub payload: Option<Payload>,
+
+    /// File to read the payload from, sent as is
+    #[structopt(long, parse(try_from_os_str = Payload::read_file))]
+    pub payload_file: Option<Payload>,
+
+    /// Regex to match on the response
+    pub regex: Option<regex::bytes::Regex>,
+}
+
+/// Bytes to send to the target
+#[derive(Debug, Clone)]
+pub struct Payload(pub Vec<u8>);
+
+impl Payload {
+    fn read_file(path: &OsStr) -> Result<Self, OsString> {
+        let bytes = fs::read(path).map_err(|e| OsString::from(format!("{:?}: {}", path, e)))?;
+        if bytes.is_empty() {
+            return Err(OsString::from(format!("Empty payload file {:?}", path)));
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl FromStr for Payload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = Vec::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                let mut utf8 = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                continue;
+            }
+            match chars.next() {
+                Some('r') => bytes.push(b'\r'),
+                Some('n') => bytes.push(b'\n'),
+                Some('t') => bytes.push(b'\t'),
+                Some('0') => bytes.push(0),
+                Some('\\') => bytes.push(b'\\'),
+                Some('x') => {
+                    // 必须正好两位十六进制数，from_str_radix 还接受 '+' 前缀
+                    let hex: String = chars.by_ref().take(2).collect();
+                    if hex.len() != 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        return Err(format!("Invalid escape \\x{} in payload {:?}", hex, s));
+                    }
+                    bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+                }
+                Some(c) => return Err(format!("Invalid escape \\{} in payload {:?}", c, s)),
+                None => return Err(format!("Trailing backslash in payload {:?}", s)),
+            }
+        }
+        if bytes.is_empty() {
+            return Err("Empty payload".to_string());
+        }
+        Ok(Self(bytes))
+    }
+}
+
+/// Service and version detection with the probes and match rules of an nmap-service-probes file
+///
+/// Unlike nmap, all probes of a target are sent one after the other on a single connection,
+/// so services that close the connection or stop answering after an unknown request are only
+/// identified by the probes sent before that. The totalwaitms of the probes is ignored,
+/// --read-timeout-sec applies to each response instead.
+#[derive(Debug, Clone, structopt::StructOpt)]
+pub struct ServiceScanOptions {
+    /// File in the nmap-service-probes format
+    #[structopt(long, parse(from_os_str), default_value = "/usr/share/nmap/nmap-service-probes")]
+    pub probes_file: std::path::PathBuf,
+
+    /// Probes with a rarity above this value are only sent to the ports they list, from 0 to 9
+    #[structopt(long, default_value = "7", parse(try_from_str = parse_intensity))]
+    pub intensity: u8,
+
+    /// Maximum number of probes to send after waiting for a banner, on the same connection
+    #[structopt(long, default_value = "3")]
+    pub max_probes: usize,
+}
+
+fn parse_intensity(s: &str) -> Result<u8, String> {
+    match s.parse::<u8>() {
+        // 和 nmap 的 --version-intensity 一样，rarity 的取值范围是 1 到 9
+        Ok(intensity) if intensity <= 9 => Ok(intensity),
+        Ok(_) => Err("Intensity must be from 0 to 9".to_string()),
+        Err(e) => Err(format!("Invalid intensity {:?}: {}", s, e)),
+    }
+}
+
+/// HTTP download throughput test
+#[derive(Debug, Clone, structopt::StructOpt)]
+pub struct SpeedTestScanOptions {
+    #[structopt(long = "req-uri", default_value = "/")]
+    pub request_uri: String,
+
+    /// Host header to send, defaults to the target address
+    #[structopt(long = "req-host")]
+    pub request_host: Option<String>,
+
+    #[structopt(
+        long = "req-header",
+        help = "HTTP header to set in request, in 'key: value' form"
+    )]
+    pub request_headers: Vec<RequestHttpHeader>,
+
+    /// Stop reading after this many bytes have been received
+    #[structopt(long, default_value = "10485760")]
+    pub max_bytes: u64,
+
+    /// Stop reading after this many seconds
+    #[structopt(long = "max-time-sec", default_value = "10")]
+    pub max_time_secs: u64,
+}
+
 /// SSH version scan
 #[derive(Debug, Clone, structopt::StructOpt)]
 pub struct SshVersionScanOptions {
@@ -151,4 +521,141 @@ pub struct SshVersionScanOptions {
 
 /// TCP connect scan
 #[derive(Debug, Clone, structopt::StructOpt)]
-pub struct TcpConnectScanOptions {}
+pub struct TcpConnectScanOptions {
+    #[structopt(flatten)]
+    pub rank: RankOptions,
+}
+
+/// TLS handshake scan reporting the ServerHello and the certificate, without completing the handshake
+#[derive(Debug, Clone, structopt::StructOpt)]
+pub struct TlsScanOptions {
+    /// Server name to send in the SNI extension
+    #[structopt(long)]
+    pub sni: Option<String>,
+
+    /// Protocols to offer with ALPN, as a comma separated list, eg. 'h2,http/1.1'
+    #[structopt(long, use_delimiter = true, parse(try_from_str = parse_alpn_protocol))]
+    pub alpn: Vec<String>,
+
+    /// Highest TLS version to offer. TLS 1.3 encrypts the certificate, offer 1.2 to read it
+    #[structopt(long, default_value = "1.3", possible_values(&["1.2", "1.3"]))]
+    pub max_version: String,
+}
+
+fn parse_alpn_protocol(s: &str) -> Result<String, String> {
+    // ClientHello 中每个协议名前是一个字节的长度，不能为空
+    if (1..=255).contains(&s.len()) {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "Invalid ALPN protocol {:?}: must be 1 to 255 bytes long",
+            s
+        ))
+    }
+}
+
+// Ranking of responsive IPs after a multi round scan
+// (not a doc comment, it would override the about text of the subcommands flattening it)
+#[derive(Debug, Clone, structopt::StructOpt)]
+pub struct RankOptions {
+    /// Only report the best N IPs
+    #[structopt(long)]
+    pub top: Option<usize>,
+
+    /// Drop IPs whose average latency is above this value, in milliseconds
+    #[structopt(long)]
+    pub max_latency_ms: Option<u64>,
+
+    /// Drop IPs whose loss rate is above this value, in percent
+    #[structopt(long)]
+    pub max_loss: Option<f64>,
+
+    /// Write the ranking to this file instead of stdout
+    #[structopt(long)]
+    pub rank_output: Option<std::path::PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_list() {
+        assert_eq!("443".parse::<PortList>().unwrap().0, [443]);
+        assert_eq!(
+            "8000-8002,80, 443,80".parse::<PortList>().unwrap().0,
+            [80, 443, 8000, 8001, 8002]
+        );
+        assert_eq!("1-1".parse::<PortList>().unwrap().0, [1]);
+        assert!("".parse::<PortList>().is_err());
+        assert!("80,".parse::<PortList>().is_err());
+        assert!("8100-8000".parse::<PortList>().is_err());
+        assert!("65536".parse::<PortList>().is_err());
+        assert!("0".parse::<PortList>().is_err());
+        assert!("0-10".parse::<PortList>().is_err());
+        assert!("http".parse::<PortList>().is_err());
+    }
+
+    #[test]
+    fn test_parse_payload() {
+        assert_eq!("PING\\r\\n".parse::<Payload>().unwrap().0, b"PING\r\n");
+        assert_eq!(
+            "\\x00\\x01\\xfF\\0\\t\\\\x".parse::<Payload>().unwrap().0,
+            b"\x00\x01\xff\0\t\\x"
+        );
+        assert_eq!("é".parse::<Payload>().unwrap().0, "é".as_bytes());
+        assert!("".parse::<Payload>().is_err());
+        assert!("a\\".parse::<Payload>().is_err());
+        assert!("\\q".parse::<Payload>().is_err());
+        assert!("\\x4".parse::<Payload>().is_err());
+        assert!("\\x4g".parse::<Payload>().is_err());
+        assert!("\\x+1".parse::<Payload>().is_err());
+    }
+
+    #[test]
+    fn test_parse_wait_ms() {
+        assert_eq!(parse_wait_ms("1"), Ok(1));
+        assert_eq!(parse_wait_ms("500"), Ok(500));
+        assert!(parse_wait_ms("0").is_err());
+        assert!(parse_wait_ms("-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_intensity() {
+        assert_eq!(parse_intensity("0"), Ok(0));
+        assert_eq!(parse_intensity("9"), Ok(9));
+        assert!(parse_intensity("10").is_err());
+        assert!(parse_intensity("256").is_err());
+    }
+
+    #[test]
+    fn test_parse_alpn_protocol() {
+        assert_eq!(parse_alpn_protocol("h2"), Ok("h2".to_string()));
+        assert!(parse_alpn_protocol(&"a".repeat(255)).is_ok());
+        assert!(parse_alpn_protocol(&"a".repeat(256)).is_err());
+        assert!(parse_alpn_protocol("").is_err());
+    }
+
+    #[test]
+    fn test_parse_shard() {
+        let shard = "2/3".parse::<Shard>().unwrap();
+        assert_eq!((shard.index, shard.count), (1, 3));
+        assert!("0/3".parse::<Shard>().is_err());
+        assert!("4/3".parse::<Shard>().is_err());
+        assert!("1/0".parse::<Shard>().is_err());
+        assert!("1".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn test_shard_select() {
+        let mut all = Vec::new();
+        for i in 1..=3 {
+            let shard = format!("{}/3", i).parse::<Shard>().unwrap();
+            let part: Vec<u64> = shard.select(0..10).collect();
+            assert_eq!(part.len() as u64, shard.len(10));
+            all.extend(part);
+        }
+        all.sort_unstable();
+        assert_eq!(all, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,141 @@
+//! 用乘法循环群生成目标的随机排列，和 zmap 的做法一样只需要 O(1) 的内存
+//!
+//! 对于素数 p，模 p 乘法群 (Z/pZ)* 是 p - 1 阶的循环群，从任意元素出发反复乘以一个原根，
+//! 恰好不重复地经过 1..p 的每个数一次。选取比目标数 n 大的素数 p，把群元素 x 映射为下标 x - 1，
+//! 跳过不小于 n 的下标，就得到了 0..n 的一个排列。原根和起点由种子决定，所以同一个种子的顺序总是相同的
+
+/// 素数 p 以及 p - 1 的全部素因子，每一项的 p 都略大于 2 的幂，最后一项是小于 2^64 的最大素数
+const GROUPS: [(u64, &[u64]); 15] = [
+    (257, &[2]),
+    (4099, &[2, 3, 683]),
+    (65537, &[2]),
+    (1048583, &[2, 29, 101, 179]),
+    (16777259, &[2, 23, 103, 3541]),
+    (268435459, &[2, 3, 19, 87211]),
+    (4294967311, &[2, 3, 5, 131, 364289]),
+    (68719476767, &[2, 163, 883, 238727]),
+    (1099511627791, &[2, 3, 5, 36650387593]),
+    (17592186044423, &[2, 11, 53, 97, 155542661]),
+    (281474976710677, &[2, 3, 7, 1361, 2462081249]),
+    (4503599627370517, &[2, 3, 23, 612229, 987127]),
+    (72057594037928017, &[2, 3, 7, 61, 34501, 14557303]),
+    (1152921504606847009, &[2, 3, 11, 683, 2971, 48912491]),
+    (18446744073709551557, &[2, 11, 137, 547, 5594472617641]),
+];
+
+/// 以随机顺序不重复地遍历 0..n 的每个整数
+#[derive(Debug, Clone)]
+pub struct Cyclic {
+    prime: u64,
+    generator: u64,
+    current: u64,
+    remaining: u64, // 还没有经过的群元素个数
+    n: u64,
+}
+
+impl Cyclic {
+    // n 超过最大的群时返回 None
+    pub fn new(n: u64, seed: u64) -> Option<Self> {
+        let (prime, factors) = *GROUPS.iter().find(|(p, _)| *p > n)?;
+        let mut rng = SplitMix64(seed);
+        let generator = loop {
+            let g = 2 + rng.next() % (prime - 2);
+            if is_primitive_root(g, prime, factors) {
+                break g;
+            }
+        };
+        Some(Self {
+            prime,
+            generator,
+            current: 1 + rng.next() % (prime - 1),
+            remaining: prime - 1,
+            n,
+        })
+    }
+}
+
+impl Iterator for Cyclic {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            let index = self.current - 1;
+            self.current = mul_mod(self.current, self.generator, self.prime);
+            if index < self.n {
+                return Some(index);
+            }
+        }
+        None
+    }
+}
+
+// g 的阶为 p - 1，即对 p - 1 的每个素因子 q 都有 g^((p - 1) / q) != 1
+fn is_primitive_root(g: u64, p: u64, factors: &[u64]) -> bool {
+    factors.iter().all(|q| pow_mod(g, (p - 1) / q, p) != 1)
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1;
+    base %= m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+/// 从种子生成原根和起点用的伪随机数，TLS 扫描也用它生成 ClientHello 中的随机字节
+pub struct SplitMix64(pub u64);
+
+impl SplitMix64 {
+    pub fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cyclic_permutation() {
+        for n in [0, 1, 2, 255, 256, 1000, 65536, 100_000] {
+            let mut seen: Vec<u64> = Cyclic::new(n, 42).unwrap().collect();
+            assert_eq!(seen.len() as u64, n);
+            seen.sort_unstable();
+            assert!(seen.iter().copied().eq(0..n));
+        }
+    }
+
+    #[test]
+    fn test_cyclic_seed() {
+        let order = |seed| Cyclic::new(1000, seed).unwrap().collect::<Vec<_>>();
+        assert_eq!(order(1), order(1));
+        assert_ne!(order(1), order(2));
+        assert_ne!(order(1), (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_cyclic_groups() {
+        assert!(Cyclic::new(u64::MAX, 0).is_none());
+        assert!(Cyclic::new(GROUPS[14].0 - 1, 0).is_some());
+        // 3 是 257 的原根，2 的阶只有 16
+        assert!(is_primitive_root(3, 257, GROUPS[0].1));
+        assert!(!is_primitive_root(2, 257, GROUPS[0].1));
+        for (p, _) in GROUPS {
+            assert_eq!(pow_mod(3, p - 1, p), 1, "{} is not prime", p);
+        }
+    }
+}
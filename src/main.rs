@@ -3,25 +3,44 @@
 use std::cmp::{max, min};
 use std::fmt::Write;
 use std::io;
-use std::net::SocketAddrV4;
 use std::os::fd::AsRawFd;
-use std::time::Duration;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use indicatif::{HumanDuration, ProgressBar, ProgressState, ProgressStyle};
 use io_uring::types::Timespec;
 use io_uring::{IoUring, Probe};
-use iprange::IpRange;
-use nix::sys::{resource, socket::SockaddrIn};
+use nix::sys::{
+    resource,
+    socket::{SockaddrLike, SockaddrStorage},
+};
 use structopt::StructOpt;
 
+use checkpoint::{Checkpoint, Position};
+use result::{ResultSink, ScanType};
+use scan::banner::ScanBanner;
 use scan::http_header_match::ScanHttpHeaderMatch;
-use scan::ssh_version::ScanSshVersion;
+use scan::probe::ScanProbe;
+use scan::service::ScanService;
+use scan::speed_test::ScanSpeedTest;
 use scan::tcp_connect::ScanTcpConnect;
+use scan::tls::ScanTls;
 use scan::{can_push, Scan};
 
+mod checkpoint;
 mod config;
+mod cyclic;
+mod metrics;
+mod rank;
+mod result;
 mod ring;
 mod scan;
+mod service_probes;
+mod signal;
+mod stats;
+mod summary;
+mod target;
+mod tls;
 
 fn main() -> io::Result<()> {
     // 初始化日志记录器
@@ -48,13 +67,33 @@ fn main() -> io::Result<()> {
 
     // 根据命令行参数选择对应的扫描类型
     let mut scan: Box<dyn Scan> = match &cl_opts.scan_opts {
+        config::ScanOptions::Banner(scan_opts) => Box::new(ScanBanner::new(
+            ScanType::Banner,
+            scan_opts.regex.as_ref(),
+            scan_opts.wait_ms,
+        )),
         config::ScanOptions::HttpHeaderMatch(scan_opts) => {
             Box::new(ScanHttpHeaderMatch::new(scan_opts))
         }
-        config::ScanOptions::SshVersion(scan_opts) => Box::new(ScanSshVersion::new(scan_opts)),
-        config::ScanOptions::TcpConnect(_) => Box::new(ScanTcpConnect::new()),
+        config::ScanOptions::Probe(scan_opts) => Box::new(ScanProbe::new(scan_opts)),
+        config::ScanOptions::Service(scan_opts) => Box::new(ScanService::new(scan_opts)?),
+        config::ScanOptions::SpeedTest(scan_opts) => Box::new(ScanSpeedTest::new(scan_opts)),
+        config::ScanOptions::SshVersion(scan_opts) => Box::new(ScanBanner::new(
+            ScanType::SshVersion,
+            scan_opts.regex.as_ref(),
+            None,
+        )),
+        config::ScanOptions::TcpConnect(scan_opts) => Box::new(ScanTcpConnect::new(scan_opts)),
+        config::ScanOptions::Tls(scan_opts) => Box::new(ScanTls::new(scan_opts)),
     };
 
+    // 根据命令行参数选择结果的输出格式和文件
+    let mut sink = result::sink(
+        cl_opts.output_format,
+        cl_opts.output.as_deref(),
+        cl_opts.resume.is_some(),
+    )?;
+
     // 创建 Probe 并检查所选的扫描类型是否支持 io_uring 提供的操作
     let mut probe = Probe::new();
     iorings.submitter().register_probe(&mut probe)?;
@@ -69,13 +108,113 @@ fn main() -> io::Result<()> {
         &iorings.submitter(),
     );
 
-    // 生成将要扫描的 IP 列表，并为每个 IP 地址创建 SockaddrIn 结构表示地址
-    // ip_ranges 是收集全部的 CIDRs 后再生成新的 CIDRs，顺便去重了
-    let ip_ranges = cl_opts.ip_subnets.iter().copied().collect::<IpRange<_>>();
-    let total_ip_count: usize = ip_ranges.iter().map(|r| r.hosts().count()).sum();
-    let mut ip_iter = ip_ranges.iter().flat_map(|r| r.hosts());
+    // 生成将要扫描的 IP 列表，并为每个 IP 地址创建 SockaddrStorage 结构表示地址
+    // targets 是收集全部的 CIDRs 后再生成新的 CIDRs，顺便去重了
+    let mut targets = target::Targets::new(&cl_opts.ports.0);
+    for net in &cl_opts.ip_subnets {
+        targets.add_net(*net);
+    }
+    for path in &cl_opts.target_files {
+        targets.load_file(path)?;
+    }
+    // 从目标中去掉排除列表和默认的保留地址段
+    for net in &cl_opts.exclude {
+        targets.exclude_net(*net);
+    }
+    for path in &cl_opts.exclude_files {
+        targets.load_exclude_file(path)?;
+    }
+    if !cl_opts.no_default_exclude {
+        targets.exclude_reserved();
+    }
+    let excluded_count = targets.finalize();
+    if excluded_count > 0 {
+        log::info!("Excluded {excluded_count} targets");
+    }
+    if targets.count() == 0 && excluded_count > 0 && !cl_opts.no_default_exclude {
+        log::warn!("All targets are excluded, use --no-default-exclude to scan reserved ranges");
+    }
+    let total_ip_count = u64::try_from(targets.count()).unwrap_or(u64::MAX);
+    // 只扫描自己的分片
+    let shard = cl_opts.shard.unwrap_or(config::Shard { index: 0, count: 1 });
+    let total_ip_count = shard.len(total_ip_count);
 
-    let progress = ProgressBar::new(total_ip_count as u64 * cl_opts.time as u64);
+    // 从检查点继续扫描时，没有指定种子就使用检查点中的种子
+    let resume = match &cl_opts.resume {
+        Some(path) => Some(Checkpoint::load(path)?),
+        None => None,
+    };
+
+    // 随机打乱目标的顺序，避免连续扫描同一个网段，没有指定种子时随机生成一个
+    let seed = cl_opts
+        .seed
+        .or_else(|| resume.as_ref().map(|c| c.seed))
+        .unwrap_or_else(|| {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            now.as_nanos() as u64 ^ u64::from(std::process::id())
+        });
+    log::info!("Random target order seed: {seed}");
+    if targets.iter_random(seed).is_none() {
+        log::warn!("Too many targets to randomize their order, scanning them sequentially");
+    }
+
+    // 恢复检查点中的进度和累积的结果
+    let rounds = max(cl_opts.time, 1);
+    let scan_type = cl_opts.scan_opts.scan_type();
+    let mut start = (0, 0, Vec::new());
+    if let Some(checkpoint) = resume {
+        if checkpoint.scan_type != scan_type || checkpoint.rounds != rounds {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Checkpoint was saved by a {} scan with --time {}",
+                    checkpoint.scan_type, checkpoint.rounds
+                ),
+            ));
+        }
+        if checkpoint.seed != seed
+            || checkpoint.shard != (shard.index, shard.count)
+            || checkpoint.target_count != total_ip_count
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Checkpoint was saved by a scan with different targets, --seed or --shard",
+            ));
+        }
+        log::info!(
+            "Resuming from round {} at target {}/{}, skipping {} targets completed after it",
+            checkpoint.round + 1,
+            checkpoint.position,
+            total_ip_count,
+            checkpoint.completed.len()
+        );
+        start = (checkpoint.round, checkpoint.position, checkpoint.completed);
+        scan.load_results(checkpoint.results)?;
+        sink.load_results(checkpoint.output)?;
+    }
+    let checkpoint_path = cl_opts.checkpoint.as_ref().or(cl_opts.resume.as_ref());
+    let checkpoint_interval = Duration::from_secs(cl_opts.checkpoint_interval_secs);
+    let save_checkpoint = |round: u8, pos: &Position, scan: &dyn Scan, sink: &dyn ResultSink| {
+        let Some(path) = checkpoint_path else {
+            return Ok(());
+        };
+        log::debug!("Saving checkpoint to {:?}", path);
+        Checkpoint {
+            scan_type,
+            seed,
+            shard: (shard.index, shard.count),
+            target_count: total_ip_count,
+            rounds,
+            round,
+            position: pos.get(),
+            completed: pos.completed(),
+            results: scan.save_results(),
+            output: sink.save_results(),
+        }
+        .save(path)
+    };
+
+    let progress = ProgressBar::new(total_ip_count.saturating_mul(cl_opts.time as u64));
     progress.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -107,6 +246,12 @@ fn main() -> io::Result<()> {
                 },
             ),
     );
+    progress.set_position(
+        total_ip_count
+            .saturating_mul(start.0 as u64)
+            .saturating_add(start.1)
+            .saturating_add(start.2.len() as u64),
+    );
 
     // 创建超时选项
     let timeouts = scan::Timeouts {
@@ -115,41 +260,72 @@ fn main() -> io::Result<()> {
         write: Timespec::new().sec(cl_opts.timeout_write_secs),
     };
 
+    // 收到 SIGINT 或 SIGTERM 后停止扫描新的目标，等待进行中的操作完成后输出结果
+    signal::install()?;
+    let mut stopping = false;
+    // 扫描过程的统计，结束时输出
+    let mut summary = summary::ScanSummary::new();
+    // 定期写入文件的 Prometheus 指标
+    let mut metrics = metrics::Metrics::default();
+    let metrics_interval = Duration::from_secs(cl_opts.metrics_interval_secs);
+    let mut last_metrics = Instant::now();
+
     // 循环次数
-    for _ in 0..max(cl_opts.time, 1) {
-        let ip_ranges: Vec<std::net::Ipv4Addr> = ip_ranges
-            .clone()
-            .into_iter()
-            .flat_map(|r| r.hosts())
-            .collect();
-        let mut ip_iter_inter = ip_ranges.into_iter();
+    let mut last_checkpoint = Instant::now();
+    for round in start.0..rounds {
+        // 继续扫描时跳过检查点之前，以及检查点之后中断前已经完成的目标
+        let (skip, completed) = if round == start.0 {
+            (start.1, std::mem::take(&mut start.2))
+        } else {
+            (0, Vec::new())
+        };
+        let ip_iter: Box<dyn Iterator<Item = SocketAddr>> = match targets.iter_random(seed) {
+            Some(iter) => Box::new(iter),
+            None => Box::new(targets.iter()),
+        };
+        let mut ip_iter_inter = shard.select(ip_iter).skip(skip as usize);
+        let mut position = Position::new(skip, completed);
 
         let mut done = false;
         // 进入 while 循环，只要 done 标志为 false，则继续循环。
         while !done {
+            if !stopping && signal::received().is_some() {
+                stopping = true;
+                log::warn!(
+                    "Interrupted, waiting for {} in-flight operations, interrupt again to exit immediately",
+                    ring_allocator.allocated_entry_count()
+                );
+            }
+
             // 内部 while 循环中调用 `can_push` 函数，
             // 该函数用于检查 Ring Buffer 是否可以推入下一个操作，而不会阻塞。如果可以，则执行以下操作。
             while can_push(&iorings.submission(), &*scan, &ring_allocator) {
-                // 调用 `ip_iter.next()` 从 IP 地址列表中获取下一个地址，
-                if let Some(ip_addr) = ip_iter_inter.next() {
-                    // 使用 SockaddrIn 结构体表示该 IP 地址和端口，
-                    let addr: SockaddrIn =
-                        SockaddrIn::from(SocketAddrV4::new(ip_addr, cl_opts.port));
-                    // 调用 `scan.socket()` 获取一个 socket 对象。
-                    let sckt = scan.socket();
+                // 调用 `ip_iter.next()` 从目标列表中获取下一个 (IP, 端口)，停止时不再推入新的目标
+                let mut next = if stopping { None } else { ip_iter_inter.next() };
+                while next.is_some() && position.skip_completed() {
+                    next = ip_iter_inter.next();
+                }
+                if let Some(target_addr) = next {
+                    // 使用 SockaddrStorage 结构体表示该 IP 地址和端口，
+                    let addr = SockaddrStorage::from(target_addr);
+                    // 调用 `scan.socket()` 获取一个与地址族一致的 socket 对象。
+                    let sckt = scan.socket(addr.family().unwrap());
                     // 记录 socket id，用于调试。
                     log::trace!("New socket: {}", sckt);
+                    position.push(sckt);
 
-                    // 执行 `scan.push_scan_ops` 方法，将 socket 和 SockaddrIn 对象推入 Ring Buffer 中，
+                    // 执行 `scan.push_scan_ops` 方法，将 socket 和 SockaddrStorage 对象推入 Ring Buffer 中，
                     // 并设置超时选项，该方法在添加操作时可能会阻塞。
-                    scan.push_scan_ops(
-                        sckt.as_raw_fd(),
-                        &addr,
-                        &mut iorings.submission(),
-                        &mut ring_allocator,
-                        &timeouts,
-                    )
-                    .expect("Failed to push ring ops");
+                    let count = scan
+                        .push_scan_ops(
+                            sckt.as_raw_fd(),
+                            &addr,
+                            &mut iorings.submission(),
+                            &mut ring_allocator,
+                            &timeouts,
+                        )
+                        .expect("Failed to push ring ops");
+                    summary.add_submitted(count, ring_allocator.allocated_entry_count());
                     // 如果没有已经分配的空间，即整个Ring Buffer 都是空的
                     // 则将 `done` 标志设置为 true，然后跳出内部 while 循环。
 
@@ -174,11 +350,16 @@ fn main() -> io::Result<()> {
             //     ring_allocator.allocated_entry_count() - completed_count,
             // ))?;
 
-            // 阻塞等待至少一个完成事件或者没有事件可以退出了
-            iorings.submit_and_wait(min(
+            // 阻塞等待至少一个完成事件或者没有事件可以退出了，等待被信号中断时回到循环开头处理
+            match iorings.submit_and_wait(min(
                 1,
                 ring_allocator.allocated_entry_count() - completed_count,
-            ))?;
+            )) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                res => {
+                    res?;
+                }
+            }
 
             // 输出当前完成任务数量。
             log::debug!("Completed count after wait: {}", iorings.completion().len());
@@ -187,17 +368,83 @@ fn main() -> io::Result<()> {
             for ce in iorings.completion() {
                 // 调用 `ring_allocator.get_entry` 函数获取相关的扫描项，
                 let entry: &ring::EntryInfo = ring_allocator.get_entry(ce.user_data()).unwrap();
+                summary.add_completed(entry.step, ce.result());
                 // 调用 `scan.process_completed_entry` 处理完成的事件并更新进度条。
-                if scan.process_completed_entry(&ce, entry, &ring_allocator) {
+                if let Some(mut result) = scan.process_completed_entry(&ce, entry, &ring_allocator) {
                     progress.inc(1);
+                    position.done(entry.fd);
+                    // 输出该目标的结果
+                    result.round = round;
+                    result.time = SystemTime::now();
+                    summary.add_target(result.state);
+                    metrics.add(&result);
+                    sink.write(&result)?;
                 }
                 // 调用 `ring_allocator.free_entry` 释放扫描项。
                 ring_allocator.free_entry(ce.user_data());
             }
+
+            // 推入扫描在处理完成事件时产生的后续操作，先于新的 IP 使用刚释放的 entry
+            let count =
+                scan.push_followup_ops(&mut iorings.submission(), &mut ring_allocator, &timeouts);
+            summary.add_submitted(count, ring_allocator.allocated_entry_count());
+
+            // 定期更新指标文件
+            if let Some(path) = &cl_opts.metrics_file {
+                if last_metrics.elapsed() >= metrics_interval {
+                    metrics.save(path, &ring_allocator)?;
+                    last_metrics = Instant::now();
+                }
+            }
+
+            // 定期保存检查点
+            if checkpoint_path.is_some() && last_checkpoint.elapsed() >= checkpoint_interval {
+                save_checkpoint(round, &position, &*scan, &*sink)?;
+                last_checkpoint = Instant::now();
+            }
         }
+
+        if stopping {
+            // 进行中的目标都已经完成，检查点可以从下一个目标继续
+            save_checkpoint(round, &position, &*scan, &*sink)?;
+            break;
+        }
+    }
+
+    if stopping {
+        progress.abandon();
+        log::warn!(
+            "Scan interrupted after {}/{} targets",
+            progress.position(),
+            progress.length().unwrap_or(0)
+        );
+    } else {
+        // 保存扫描完成时的结果，用这个检查点继续扫描只会输出结果
+        save_checkpoint(rounds, &Position::default(), &*scan, &*sink)?;
+        progress.finish();
+    }
+
+    summary.write(&mut io::stderr().lock(), |step| scan.step_name(step))?;
+    if let Some(path) = &cl_opts.metrics_file {
+        metrics.save(path, &ring_allocator)?;
     }
 
-    progress.finish();
+    // 输出扫描的汇总结果，非文本格式的标准输出只有结果，汇总写到标准错误
+    if stopping {
+        sink.finish_interrupted()?;
+    } else {
+        sink.finish()?;
+    }
+    if cl_opts.output_format == config::OutputFormat::Text {
+        scan.finish(&mut io::stdout().lock())?;
+    } else {
+        scan.finish(&mut io::stderr().lock())?;
+    }
+
+    // 被信号中断时以 128 + 信号值退出，和 shell 的约定一致
+    if let Some(sig) = signal::received() {
+        std::process::exit(128 + sig);
+    }
 
     Ok(())
 }
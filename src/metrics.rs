@@ -0,0 +1,217 @@
+//! Prometheus 指标，定期以文本格式写入文件，供 node_exporter 的 textfile collector 读取
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::result::{ScanResult, State};
+use crate::ring::{BufferDirection, RingAllocator};
+
+// 连接延迟直方图的上界，单位秒
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// 输出时从 RingAllocator 读取的当前值
+#[derive(Debug)]
+struct Gauges {
+    in_flight: usize,
+    free_rx_buffers: usize,
+    free_tx_buffers: usize,
+}
+
+impl From<&RingAllocator> for Gauges {
+    fn from(allocator: &RingAllocator) -> Self {
+        Self {
+            in_flight: allocator.allocated_entry_count(),
+            free_rx_buffers: allocator.free_buf_count(BufferDirection::RX),
+            free_tx_buffers: allocator.free_buf_count(BufferDirection::TX),
+        }
+    }
+}
+
+/// 扫描过程中累积的计数器
+#[derive(Debug, Default)]
+pub struct Metrics {
+    connects: [u64; State::ALL.len()], // 按端口状态统计的连接结果
+    bytes_received: u64,
+    latency_buckets: [u64; LATENCY_BUCKETS.len()], // 每个桶单独计数，输出时再累加
+    latency_count: u64,
+    latency_sum: Duration,
+}
+
+impl Metrics {
+    // 记录一个目标的结果
+    pub fn add(&mut self, result: &ScanResult) {
+        self.connects[result.state as usize] += 1;
+        self.bytes_received += match (&result.transfer, &result.banner) {
+            (Some(transfer), _) => transfer.bytes,
+            (None, Some(banner)) => banner.len() as u64,
+            (None, None) => 0,
+        };
+        if let Some(latency) = result.latency {
+            let secs = latency.as_secs_f64();
+            if let Some(i) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+                self.latency_buckets[i] += 1;
+            }
+            self.latency_count += 1;
+            self.latency_sum += latency;
+        }
+    }
+
+    // 以 Prometheus 文本格式输出
+    fn write(&self, out: &mut impl Write, gauges: &Gauges) -> io::Result<()> {
+        writeln!(
+            out,
+            "# HELP io_uring_scanner_in_flight_entries io_uring operations in flight."
+        )?;
+        writeln!(out, "# TYPE io_uring_scanner_in_flight_entries gauge")?;
+        writeln!(
+            out,
+            "io_uring_scanner_in_flight_entries {}",
+            gauges.in_flight
+        )?;
+
+        writeln!(
+            out,
+            "# HELP io_uring_scanner_free_buffers Registered buffers not in use."
+        )?;
+        writeln!(out, "# TYPE io_uring_scanner_free_buffers gauge")?;
+        for (direction, count) in [
+            ("rx", gauges.free_rx_buffers),
+            ("tx", gauges.free_tx_buffers),
+        ] {
+            writeln!(
+                out,
+                "io_uring_scanner_free_buffers{{direction=\"{direction}\"}} {count}"
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP io_uring_scanner_connects_total Scanned targets by port state."
+        )?;
+        writeln!(out, "# TYPE io_uring_scanner_connects_total counter")?;
+        for (state, count) in State::ALL.iter().zip(self.connects) {
+            writeln!(
+                out,
+                "io_uring_scanner_connects_total{{state=\"{state}\"}} {count}"
+            )?;
+        }
+
+        writeln!(
+            out,
+            "# HELP io_uring_scanner_received_bytes_total Bytes received from targets."
+        )?;
+        writeln!(out, "# TYPE io_uring_scanner_received_bytes_total counter")?;
+        writeln!(
+            out,
+            "io_uring_scanner_received_bytes_total {}",
+            self.bytes_received
+        )?;
+
+        writeln!(
+            out,
+            "# HELP io_uring_scanner_connect_latency_seconds Time to establish connections."
+        )?;
+        writeln!(
+            out,
+            "# TYPE io_uring_scanner_connect_latency_seconds histogram"
+        )?;
+        let mut cumulative = 0;
+        for (le, count) in LATENCY_BUCKETS.iter().zip(self.latency_buckets) {
+            cumulative += count;
+            writeln!(
+                out,
+                "io_uring_scanner_connect_latency_seconds_bucket{{le=\"{le}\"}} {cumulative}"
+            )?;
+        }
+        writeln!(
+            out,
+            "io_uring_scanner_connect_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.latency_count
+        )?;
+        writeln!(
+            out,
+            "io_uring_scanner_connect_latency_seconds_sum {}",
+            self.latency_sum.as_secs_f64()
+        )?;
+        writeln!(
+            out,
+            "io_uring_scanner_connect_latency_seconds_count {}",
+            self.latency_count
+        )
+    }
+
+    // 先写入临时文件再重命名，node_exporter 不会读到写了一半的文件
+    pub fn save(&self, path: &Path, allocator: &RingAllocator) -> io::Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        self.write(&mut writer, &Gauges::from(allocator))?;
+        writer.flush()?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::result::ScanType;
+
+    #[test]
+    fn test_metrics_write() {
+        let mut metrics = Metrics::default();
+        for (state, latency_ms, banner) in [
+            (State::Open, Some(3), Some(&b"SSH-2.0-OpenSSH_8.4\r\n"[..])),
+            (State::Open, Some(30), None),
+            (State::Open, Some(6000), None),
+            (State::Filtered, None, None),
+        ] {
+            let mut result = ScanResult::new("10.0.0.1:22".parse().unwrap(), ScanType::SshVersion);
+            result.state = state;
+            result.latency = latency_ms.map(Duration::from_millis);
+            result.banner = banner.map(|b| b.to_vec());
+            metrics.add(&result);
+        }
+        let gauges = Gauges {
+            in_flight: 12,
+            free_rx_buffers: 3,
+            free_tx_buffers: 4,
+        };
+        let mut out = Vec::new();
+        metrics.write(&mut out, &gauges).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let samples: Vec<_> = out.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(
+            samples,
+            [
+                "io_uring_scanner_in_flight_entries 12",
+                "io_uring_scanner_free_buffers{direction=\"rx\"} 3",
+                "io_uring_scanner_free_buffers{direction=\"tx\"} 4",
+                "io_uring_scanner_connects_total{state=\"open\"} 3",
+                "io_uring_scanner_connects_total{state=\"closed\"} 0",
+                "io_uring_scanner_connects_total{state=\"filtered\"} 1",
+                "io_uring_scanner_connects_total{state=\"unreachable\"} 0",
+                "io_uring_scanner_received_bytes_total 21",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.001\"} 0",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.0025\"} 0",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.005\"} 1",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.01\"} 1",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.025\"} 1",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.05\"} 2",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.1\"} 2",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.25\"} 2",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"0.5\"} 2",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"1\"} 2",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"2.5\"} 2",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"5\"} 2",
+                "io_uring_scanner_connect_latency_seconds_bucket{le=\"+Inf\"} 3",
+                "io_uring_scanner_connect_latency_seconds_sum 6.033",
+                "io_uring_scanner_connect_latency_seconds_count 3",
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! 多轮测速结束后对有响应的 IP 进行排名
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use crate::config::RankOptions;
+use crate::stats::LatencyStats;
+
+/// 过滤掉没有响应或超过阈值的目标，先按丢包率、再按平均延迟升序排列，最后只保留前 N 个
+pub fn rank<'a, K>(
+    stats: impl IntoIterator<Item = (K, &'a LatencyStats)>,
+    opts: &RankOptions,
+) -> Vec<(K, &'a LatencyStats)> {
+    let max_latency = opts.max_latency_ms.map(Duration::from_millis);
+    let mut ranked: Vec<_> = stats
+        .into_iter()
+        // 至少成功一轮才有延迟数据
+        .filter(|(_, s)| s.received() > 0)
+        .filter(|(_, s)| max_latency.map_or(true, |max| s.avg().map_or(false, |avg| avg <= max)))
+        .filter(|(_, s)| opts.max_loss.map_or(true, |max| s.loss_percent() <= max))
+        .collect();
+
+    // 稳定排序，丢包率和延迟都相同的目标保持传入时的顺序
+    ranked.sort_by(|(_, a), (_, b)| {
+        a.loss_percent()
+            .partial_cmp(&b.loss_percent())
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.avg().cmp(&b.avg()))
+    });
+
+    if let Some(top) = opts.top {
+        ranked.truncate(top);
+    }
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts() -> RankOptions {
+        RankOptions {
+            top: None,
+            max_latency_ms: None,
+            max_loss: None,
+            rank_output: None,
+        }
+    }
+
+    fn stats(samples_ms: &[u64], failed: usize) -> LatencyStats {
+        let mut stats = LatencyStats::default();
+        for v in samples_ms {
+            stats.record_success(Duration::from_millis(*v));
+        }
+        for _ in 0..failed {
+            stats.record_failure();
+        }
+        stats
+    }
+
+    #[test]
+    fn test_rank_order() {
+        let all = [
+            ("slow", stats(&[80, 90], 0)),
+            ("lossy", stats(&[10], 1)),
+            ("dead", stats(&[], 2)),
+            ("fast", stats(&[20, 30], 0)),
+        ];
+        let ranked = rank(all.iter().map(|(k, s)| (*k, s)), &opts());
+        let keys: Vec<_> = ranked.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, ["fast", "slow", "lossy"]);
+    }
+
+    #[test]
+    fn test_rank_filters() {
+        let all = [
+            ("slow", stats(&[80, 90], 0)),
+            ("lossy", stats(&[10], 1)),
+            ("fast", stats(&[20, 30], 0)),
+            ("fast2", stats(&[40, 40], 0)),
+        ];
+
+        let mut o = opts();
+        o.max_latency_ms = Some(50);
+        o.max_loss = Some(10.0);
+        let ranked = rank(all.iter().map(|(k, s)| (*k, s)), &o);
+        let keys: Vec<_> = ranked.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, ["fast", "fast2"]);
+
+        o.top = Some(1);
+        let ranked = rank(all.iter().map(|(k, s)| (*k, s)), &o);
+        let keys: Vec<_> = ranked.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, ["fast"]);
+    }
+}
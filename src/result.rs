@@ -0,0 +1,378 @@
+//! 统一的扫描结果，以及输出结果的 ResultSink
+
+use std::fmt::{self, Write as _};
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use bstr::ByteSlice;
+use nix::libc;
+use serde::{Deserialize, Serialize};
+
+use crate::config::OutputFormat;
+
+pub mod binary;
+pub mod csv;
+pub mod jsonl;
+pub mod list;
+pub mod text;
+pub mod xml;
+
+/// 产生结果的扫描类型，名称与子命令相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanType {
+    Banner,
+    HttpHeaderMatch,
+    Probe,
+    Service,
+    SpeedTest,
+    SshVersion,
+    TcpConnect,
+    Tls,
+}
+
+impl fmt::Display for ScanType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Banner => "banner",
+            Self::HttpHeaderMatch => "http-header-match",
+            Self::Probe => "probe",
+            Self::Service => "service",
+            Self::SpeedTest => "speed-test",
+            Self::SshVersion => "ssh-version",
+            Self::TcpConnect => "tcp-connect",
+            Self::Tls => "tls",
+        })
+    }
+}
+
+/// 目标端口的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Open,        // 连接成功
+    Closed,      // 连接被拒绝
+    Filtered,    // 连接超时，没有任何响应
+    Unreachable, // 主机或网络不可达
+}
+
+impl State {
+    pub const ALL: [State; 4] = [Self::Open, Self::Closed, Self::Filtered, Self::Unreachable];
+
+    /// 合并多轮扫描的状态，有一轮开放就认为端口开放，否则取最后一轮的状态
+    pub fn merge(self, other: State) -> State {
+        if self == Self::Open {
+            self
+        } else {
+            other
+        }
+    }
+
+    /// 根据 Connect 操作的返回值判断端口状态
+    ///
+    /// 超时时 LinkTimeout 取消 Connect，返回 ECANCELED，其他没有明确含义的错误也当作没有响应
+    pub fn from_connect(ret: i32) -> Self {
+        match -ret {
+            0 => Self::Open,
+            libc::ECONNREFUSED => Self::Closed,
+            libc::EHOSTUNREACH | libc::ENETUNREACH => Self::Unreachable,
+            _ => Self::Filtered,
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Open => "open",
+            Self::Closed => "closed",
+            Self::Filtered => "filtered",
+            Self::Unreachable => "unreachable",
+        })
+    }
+}
+
+/// 各个状态的目标数，扫描结束时输出
+#[derive(Debug, Default)]
+pub struct StateCounts([u64; State::ALL.len()]);
+
+impl StateCounts {
+    pub fn add(&mut self, state: State) {
+        self.0[state as usize] += 1;
+    }
+}
+
+impl fmt::Display for StateCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (state, count)) in State::ALL.iter().zip(self.0).enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{count} {state}")?;
+        }
+        Ok(())
+    }
+}
+
+/// 下载测速的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transfer {
+    pub bytes: u64,
+    pub duration: Duration,
+}
+
+impl Transfer {
+    pub fn bytes_per_sec(&self) -> u64 {
+        if self.duration.is_zero() {
+            self.bytes
+        } else {
+            (self.bytes as f64 / self.duration.as_secs_f64()) as u64
+        }
+    }
+}
+
+/// 用服务指纹识别出的服务，字段和 nmap 的版本信息相同
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Service {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cpe: Vec<String>,
+    pub soft: bool, // 只由 softmatch 确定了服务，没有版本信息
+}
+
+impl Service {
+    /// 只有服务名
+    pub fn named(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// 和 nmap 的 VERSION 列相同的描述，例如 `OpenSSH 8.4p1 Debian 5 (protocol 2.0)`
+    pub fn description(&self) -> String {
+        let mut parts: Vec<String> = [&self.product, &self.version]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        if let Some(info) = &self.info {
+            parts.push(format!("({info})"));
+        }
+        parts.join(" ")
+    }
+}
+
+/// TLS 握手扫描从 ServerHello 和证书中得到的信息
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsInfo {
+    pub version: String, // 协商的版本，只收到告警时为记录层的版本
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cipher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpn: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<String>, // ServerHello 中的扩展
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<Certificate>, // 只有 TLS 1.2 及以下的证书是明文
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alert: Option<String>, // 服务端拒绝握手时的告警
+}
+
+impl TlsInfo {
+    /// 一行描述，例如 `TLSv1.2 TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256 (h2)` 或 `TLSv1.2 alert handshake_failure`
+    pub fn description(&self) -> String {
+        let mut parts = vec![self.version.clone()];
+        parts.extend(self.cipher.clone());
+        if let Some(alpn) = &self.alpn {
+            parts.push(format!("({alpn})"));
+        }
+        if let Some(alert) = &self.alert {
+            parts.push(format!("alert {alert}"));
+        }
+        parts.join(" ")
+    }
+}
+
+/// 服务端证书，时间为 RFC 3339 格式的 UTC 时间
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sans: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+    pub key: String, // 公钥算法和长度，例如 `RSA 2048`
+}
+
+/// 单个目标在一轮扫描中的结果
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub addr: SocketAddr,
+    pub scan: ScanType,
+    pub round: u8, // 第几轮 `--time`，从 0 开始
+    pub state: State,
+    pub latency: Option<Duration>, // 建立连接的耗时
+    pub banner: Option<Vec<u8>>,   // 服务端返回的原始数据
+    pub rules: Vec<String>,        // 响应满足的匹配规则
+    // 捕获的响应头，值为 None 表示响应中没有该响应头或没有匹配
+    pub captures: Vec<(String, Option<String>)>,
+    pub transfer: Option<Transfer>,
+    pub service: Option<Service>, // 服务识别扫描识别出的服务
+    pub tls: Option<TlsInfo>, // TLS 扫描解析出的握手信息
+    pub matched: bool,    // 是否满足扫描的匹配条件，文本格式只输出匹配的结果
+    pub time: SystemTime, // 得到结果的时间
+}
+
+impl ScanResult {
+    pub fn new(addr: SocketAddr, scan: ScanType) -> Self {
+        Self {
+            addr,
+            scan,
+            round: 0,
+            state: State::Filtered,
+            latency: None,
+            banner: None,
+            rules: Vec::new(),
+            captures: Vec::new(),
+            transfer: None,
+            service: None,
+            tls: None,
+            matched: false,
+            time: SystemTime::now(),
+        }
+    }
+
+    /// 识别出的服务名和服务端返回的信息，HTTP 只保留响应头，通用的 banner 和 probe 扫描不识别服务
+    pub fn service_banner(&self) -> Option<(&str, &[u8])> {
+        let banner = self.banner.as_deref()?;
+        match self.scan {
+            ScanType::HttpHeaderMatch => {
+                let end = banner.find(b"\r\n\r\n").unwrap_or(banner.len());
+                Some(("http", &banner[..end]))
+            }
+            ScanType::Banner | ScanType::Probe => Some(("unknown", banner.trim_ascii_end())),
+            ScanType::Service => {
+                let name = self.service.as_ref().map_or("unknown", |s| s.name.as_str());
+                Some((name, banner.trim_ascii_end()))
+            }
+            ScanType::SshVersion => Some(("ssh", banner.trim_ascii_end())),
+            ScanType::SpeedTest | ScanType::TcpConnect | ScanType::Tls => None,
+        }
+    }
+}
+
+/// 把服务端返回的数据转成一行文本，和 nmap、masscan 一样把不可打印的字节转义成 \xNN
+pub fn printable(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b == b' ' || b.is_ascii_graphic() {
+            s.push(b as char);
+        } else {
+            write!(s, "\\x{:02X}", b).unwrap();
+        }
+    }
+    s
+}
+
+/// 扫描结果的输出
+pub trait ResultSink {
+    /// 输出一个目标的扫描结果
+    fn write(&mut self, result: &ScanResult) -> io::Result<()>;
+
+    /// 扫描结束后调用，输出结尾并刷新缓冲区
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// 扫描被中断时代替 finish 调用，之后可能从检查点继续扫描，追加到同一个文件
+    fn finish_interrupted(&mut self) -> io::Result<()> {
+        self.finish()
+    }
+
+    /// 返回保存到检查点的累积结果，逐个输出结果的格式不需要保存
+    fn save_results(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// 从检查点恢复累积的结果
+    fn load_results(&mut self, _results: serde_json::Value) -> serde_json::Result<()> {
+        Ok(())
+    }
+}
+
+/// 根据命令行选择的格式创建输出，没有指定文件时输出到标准输出
+///
+/// 从检查点继续扫描时，逐个输出结果的格式追加到文件末尾，扫描结束时才输出的格式从检查点恢复后重新写入
+pub fn sink(
+    format: OutputFormat,
+    path: Option<&Path>,
+    resume: bool,
+) -> io::Result<Box<dyn ResultSink>> {
+    let out: Box<dyn Write> = match path {
+        Some(path) => {
+            let append = resume && !matches!(format, OutputFormat::Csv | OutputFormat::Xml);
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(path)?;
+            Box::new(BufWriter::new(file))
+        }
+        None => Box::new(io::stdout()),
+    };
+    Ok(match format {
+        OutputFormat::Text => Box::new(text::TextSink::new(out)),
+        OutputFormat::Jsonl => Box::new(jsonl::JsonlSink::new(out)),
+        OutputFormat::Csv => Box::new(csv::CsvSink::new(out)),
+        OutputFormat::Xml => Box::new(xml::XmlSink::new(out)),
+        OutputFormat::List => Box::new(list::ListSink::new(out)),
+        OutputFormat::Binary => Box::new(binary::BinarySink::new(out)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_from_connect() {
+        assert_eq!(State::from_connect(0), State::Open);
+        assert_eq!(State::from_connect(-libc::ECONNREFUSED), State::Closed);
+        assert_eq!(State::from_connect(-libc::ECANCELED), State::Filtered);
+        assert_eq!(State::from_connect(-libc::ETIMEDOUT), State::Filtered);
+        assert_eq!(State::from_connect(-libc::EHOSTUNREACH), State::Unreachable);
+        assert_eq!(State::from_connect(-libc::ENETUNREACH), State::Unreachable);
+    }
+
+    #[test]
+    fn test_state_counts() {
+        let mut counts = StateCounts::default();
+        for state in [State::Open, State::Filtered, State::Open, State::Closed] {
+            counts.add(state);
+        }
+        assert_eq!(
+            counts.to_string(),
+            "2 open, 1 closed, 1 filtered, 0 unreachable"
+        );
+        assert_eq!(State::Open.merge(State::Filtered), State::Open);
+        assert_eq!(State::Closed.merge(State::Filtered), State::Filtered);
+    }
+}
@@ -0,0 +1,260 @@
+//! Masscan compatible binary output
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nix::libc;
+
+use crate::result::{ResultSink, ScanResult, State};
+
+// masscan 的记录类型，见 masscan 源码中的 out-record.h
+const OPEN_IPV4: u8 = 6;
+const BANNER_IPV4: u8 = 9;
+const OPEN_IPV6: u8 = 10;
+const BANNER_IPV6: u8 = 13;
+
+// 记录的长度最多用两个字节表示，每个字节 7 位
+const MAX_RECORD_LEN: usize = 128 * 128 - 1;
+
+// 文件的开头和结尾是类型为 'm'、长度为 'a' 的记录，所以正好是 2 + 'a' 字节
+const HEADER_LEN: usize = 2 + b'a' as usize;
+
+// 连接成功相当于收到 SYN-ACK，masscan 用 TCP 标志表示端口状态的原因
+const REASON_SYN_ACK: u8 = 0x12;
+
+/// 和 `masscan -oB` 相同的格式，可以用 `masscan --readscan` 读取并转换成 masscan 支持的其他格式
+///
+/// 和 list 格式一样，每个开放的端口一条记录，有 banner 时再输出一条 banner 记录
+pub struct BinarySink<W: Write> {
+    out: W,
+    start: SystemTime,
+    started: bool,
+    // 已经输出过的端口，多轮扫描中每个端口只输出一次
+    reported: HashSet<SocketAddr>,
+}
+
+impl<W: Write> BinarySink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            start: SystemTime::now(),
+            started: false,
+            reported: HashSet::new(),
+        }
+    }
+
+    // 第一次输出时写入文件开头，其中有扫描开始的时间
+    fn start_file(&mut self) -> io::Result<()> {
+        if !self.started {
+            let start = unix_secs(self.start);
+            self.write_header(&format!("masscan/1.1\ns:{start}\n"))?;
+            self.started = true;
+        }
+        Ok(())
+    }
+
+    // 开头和结尾的记录，用 0 填充到固定长度
+    fn write_header(&mut self, text: &str) -> io::Result<()> {
+        let mut header = [0; HEADER_LEN];
+        header[..text.len()].copy_from_slice(text.as_bytes());
+        self.out.write_all(&header)
+    }
+
+    // 类型、长度和内容，长度大于 127 时用两个字节，第一个字节的最高位为 1
+    fn write_record(&mut self, kind: u8, data: &[u8]) -> io::Result<()> {
+        let len = data.len();
+        if len < 128 {
+            self.out.write_all(&[kind, len as u8])?;
+        } else {
+            self.out
+                .write_all(&[kind, (len >> 7) as u8 | 0x80, (len & 0x7F) as u8])?;
+        }
+        self.out.write_all(data)
+    }
+}
+
+impl<W: Write> ResultSink for BinarySink<W> {
+    fn write(&mut self, result: &ScanResult) -> io::Result<()> {
+        self.start_file()?;
+        if result.state != State::Open || !self.reported.insert(result.addr) {
+            return Ok(());
+        }
+        let time = (unix_secs(result.time) as u32).to_be_bytes();
+        let port = result.addr.port().to_be_bytes();
+        let ip_proto = libc::IPPROTO_TCP as u8;
+        // 连接扫描拿不到响应的 TTL，填 0
+        let ttl = 0;
+
+        let mut open = Vec::new();
+        match result.addr.ip() {
+            IpAddr::V4(ip) => {
+                open.extend(time);
+                open.extend(ip.octets());
+                open.extend(port);
+                open.extend([REASON_SYN_ACK, ttl, ip_proto]);
+                self.write_record(OPEN_IPV4, &open)?;
+            }
+            IpAddr::V6(ip) => {
+                open.extend(time);
+                open.push(ip_proto);
+                open.extend(port);
+                open.extend([REASON_SYN_ACK, ttl, 6]);
+                open.extend(ip.octets());
+                self.write_record(OPEN_IPV6, &open)?;
+            }
+        }
+
+        let Some((service, banner)) = result.service_banner() else {
+            return Ok(());
+        };
+        let app = app_protocol(service).to_be_bytes();
+        let mut record = Vec::new();
+        let kind = match result.addr.ip() {
+            IpAddr::V4(ip) => {
+                record.extend(time);
+                record.extend(ip.octets());
+                record.push(ip_proto);
+                record.extend(port);
+                record.extend(app);
+                record.push(ttl);
+                BANNER_IPV4
+            }
+            IpAddr::V6(ip) => {
+                record.extend(time);
+                record.push(ip_proto);
+                record.extend(port);
+                record.extend(app);
+                record.extend([ttl, 6]);
+                record.extend(ip.octets());
+                BANNER_IPV6
+            }
+        };
+        // 太长的 banner 截断到记录长度的上限
+        let len = banner.len().min(MAX_RECORD_LEN - record.len());
+        record.extend_from_slice(&banner[..len]);
+        self.write_record(kind, &record)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.finish_interrupted()?;
+        self.write_header("masscan/1.1")?;
+        self.out.flush()
+    }
+
+    // 继续扫描时追加到同一个文件，结尾要等扫描完成后才输出
+    fn finish_interrupted(&mut self) -> io::Result<()> {
+        self.start_file()?;
+        self.out.flush()
+    }
+
+    // 检查点中保存已经输出过的端口，继续扫描时不再重复输出，文件开头也已经输出过了
+    fn save_results(&self) -> serde_json::Value {
+        serde_json::to_value(&self.reported).unwrap()
+    }
+
+    fn load_results(&mut self, results: serde_json::Value) -> serde_json::Result<()> {
+        if !results.is_null() {
+            self.reported = serde_json::from_value(results)?;
+            self.started = true;
+        }
+        Ok(())
+    }
+}
+
+// masscan 中应用层协议的编号，见 masscan 源码中的 masscan-app.h，没有对应的协议时用 "unknown"
+fn app_protocol(service: &str) -> u16 {
+    match service {
+        "ssh" => 3,
+        "http" => 4,
+        "ftp" => 5,
+        "ssl" => 9,
+        "smtp" => 11,
+        "pop3" => 12,
+        "imap" => 13,
+        _ => 1,
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::result::ScanType;
+
+    fn result(addr: &str, open: bool, banner: Option<&[u8]>) -> ScanResult {
+        let mut result = ScanResult::new(addr.parse().unwrap(), ScanType::SshVersion);
+        if open {
+            result.state = State::Open;
+        }
+        result.banner = banner.map(|b| b.to_vec());
+        result.time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        result
+    }
+
+    fn header(text: &[u8]) -> Vec<u8> {
+        let mut header = text.to_vec();
+        header.resize(HEADER_LEN, 0);
+        header
+    }
+
+    #[test]
+    fn test_binary_sink() {
+        let mut sink = BinarySink::new(Vec::new());
+        sink.start = UNIX_EPOCH + Duration::from_secs(1_699_999_999);
+        for r in [
+            result("10.0.0.1:22", true, Some(b"SSH-2.0-OpenSSH_8.4\r\n")),
+            result("10.0.0.2:22", false, None),
+            result("[2001:db8::1]:2222", true, None),
+            result("10.0.0.1:22", true, Some(b"SSH-2.0-OpenSSH_8.4\r\n")),
+        ] {
+            sink.write(&r).unwrap();
+        }
+        sink.finish().unwrap();
+
+        let mut expected = header(b"masscan/1.1\ns:1699999999\n");
+        expected.extend(b"\x06\x0D\x65\x53\xF1\x00\x0A\x00\x00\x01\x00\x16\x12\x00\x06");
+        expected.extend(b"\x09\x21\x65\x53\xF1\x00\x0A\x00\x00\x01\x06\x00\x16\x00\x03\x00");
+        expected.extend(b"SSH-2.0-OpenSSH_8.4");
+        expected.extend(b"\x0A\x1A\x65\x53\xF1\x00\x06\x08\xAE\x12\x00\x06");
+        expected.extend(b"\x20\x01\x0D\xB8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01");
+        expected.extend(header(b"masscan/1.1"));
+        assert_eq!(sink.out, expected);
+    }
+
+    #[test]
+    fn test_binary_sink_long_banner() {
+        let mut sink = BinarySink::new(Vec::new());
+        let banner = vec![b'a'; 20000];
+        sink.write(&result("10.0.0.1:22", true, Some(&banner)))
+            .unwrap();
+        let record = &sink.out[HEADER_LEN + 15..];
+        assert_eq!(record[..3], [BANNER_IPV4, 0xFF, 0x7F]);
+        assert_eq!(record.len(), 3 + MAX_RECORD_LEN);
+    }
+
+    #[test]
+    fn test_binary_sink_resume() {
+        let mut sink = BinarySink::new(Vec::new());
+        sink.write(&result("10.0.0.1:22", true, None)).unwrap();
+        sink.finish_interrupted().unwrap();
+        let saved = sink.save_results();
+
+        let mut resumed = BinarySink::new(std::mem::take(&mut sink.out));
+        resumed.load_results(saved).unwrap();
+        resumed.write(&result("10.0.0.1:22", true, None)).unwrap();
+        resumed.finish().unwrap();
+        let len = resumed.out.len();
+        assert_eq!(len, 2 * HEADER_LEN + 15);
+        assert_eq!(resumed.out[len - HEADER_LEN..], header(b"masscan/1.1"));
+    }
+}
@@ -0,0 +1,194 @@
+//! CSV output
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::result::{ResultSink, ScanResult, State};
+use crate::stats::LatencyStats;
+
+/// 每个目标一行的汇总表，多轮扫描的结果合并成状态、发送、收到、丢包率和平均延迟，扫描结束后输出
+///
+/// 捕获的响应头（例如 CF-RAY 中的机房代码）各占一列
+pub struct CsvSink<W: Write> {
+    out: W,
+    rows: BTreeMap<SocketAddr, Row>,
+}
+
+// 一个目标在所有轮次中累积的结果
+#[derive(Debug, Serialize, Deserialize)]
+struct Row {
+    state: State,
+    stats: LatencyStats,
+    captures: BTreeMap<String, String>, // 最后一次捕获到的值
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            rows: BTreeMap::new(),
+        }
+    }
+}
+
+impl<W: Write> ResultSink for CsvSink<W> {
+    fn write(&mut self, result: &ScanResult) -> io::Result<()> {
+        let row = self.rows.entry(result.addr).or_insert_with(|| Row {
+            state: result.state,
+            stats: LatencyStats::default(),
+            captures: BTreeMap::new(),
+        });
+        row.state = row.state.merge(result.state);
+        match (result.state, result.latency) {
+            (State::Open, Some(latency)) => row.stats.record_success(latency),
+            _ => row.stats.record_failure(),
+        }
+        for (key, value) in &result.captures {
+            if let Some(value) = value {
+                row.captures.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let keys: BTreeSet<_> = self
+            .rows
+            .values()
+            .flat_map(|row| row.captures.keys())
+            .collect();
+        write!(
+            self.out,
+            "ip,port,state,sent,received,loss_percent,avg_latency_ms"
+        )?;
+        for key in &keys {
+            write!(self.out, ",{}", field(key))?;
+        }
+        writeln!(self.out)?;
+
+        for (addr, row) in &self.rows {
+            write!(
+                self.out,
+                "{},{},{},{},{},{:.2},",
+                addr.ip(),
+                addr.port(),
+                row.state,
+                row.stats.sent(),
+                row.stats.received(),
+                row.stats.loss_percent()
+            )?;
+            if let Some(avg) = row.stats.avg() {
+                write!(self.out, "{:.3}", avg.as_secs_f64() * 1000.0)?;
+            }
+            for key in &keys {
+                let value = row.captures.get(*key).map_or("", String::as_str);
+                write!(self.out, ",{}", field(value))?;
+            }
+            writeln!(self.out)?;
+        }
+        self.out.flush()
+    }
+
+    // 汇总表在扫描结束时才输出，检查点中保存已经累积的行
+    fn save_results(&self) -> serde_json::Value {
+        serde_json::to_value(&self.rows).unwrap()
+    }
+
+    fn load_results(&mut self, results: serde_json::Value) -> serde_json::Result<()> {
+        if !results.is_null() {
+            self.rows = serde_json::from_value(results)?;
+        }
+        Ok(())
+    }
+}
+
+// 包含逗号、引号或换行的字段用引号括起来，见 RFC 4180
+fn field(value: &str) -> String {
+    if value.contains(&[',', '"', '\r', '\n'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::result::ScanType;
+
+    fn result(addr: &str, latency_ms: Option<u64>) -> ScanResult {
+        let mut result = ScanResult::new(addr.parse().unwrap(), ScanType::TcpConnect);
+        if let Some(ms) = latency_ms {
+            result.state = State::Open;
+            result.latency = Some(Duration::from_millis(ms));
+        }
+        result
+    }
+
+    fn csv(sink: &mut CsvSink<Vec<u8>>) -> String {
+        sink.finish().unwrap();
+        String::from_utf8(std::mem::take(&mut sink.out)).unwrap()
+    }
+
+    #[test]
+    fn test_csv_sink() {
+        let mut sink = CsvSink::new(Vec::new());
+        for r in [
+            result("10.0.0.2:443", Some(10)),
+            result("10.0.0.1:443", None),
+            result("10.0.0.2:443", Some(20)),
+            result("10.0.0.1:443", None),
+        ] {
+            sink.write(&r).unwrap();
+        }
+        assert_eq!(
+            csv(&mut sink),
+            "ip,port,state,sent,received,loss_percent,avg_latency_ms\n\
+             10.0.0.1,443,filtered,2,0,100.00,\n\
+             10.0.0.2,443,open,2,2,0.00,15.000\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_sink_captures() {
+        let mut sink = CsvSink::new(Vec::new());
+        let mut sjc = result("[2606:4700::1]:443", Some(1));
+        sjc.captures = vec![("CF-RAY".to_string(), Some("SJC".to_string()))];
+        let mut missing = result("10.0.0.1:443", Some(2));
+        missing.captures = vec![("CF-RAY".to_string(), None)];
+        let mut quoted = result("10.0.0.2:443", Some(3));
+        quoted.captures = vec![("CF-RAY".to_string(), Some("a,\"b\"".to_string()))];
+        for r in [sjc, missing, quoted] {
+            sink.write(&r).unwrap();
+        }
+        assert_eq!(
+            csv(&mut sink),
+            "ip,port,state,sent,received,loss_percent,avg_latency_ms,CF-RAY\n\
+             10.0.0.1,443,open,1,1,0.00,2.000,\n\
+             10.0.0.2,443,open,1,1,0.00,3.000,\"a,\"\"b\"\"\"\n\
+             2606:4700::1,443,open,1,1,0.00,1.000,SJC\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_sink_save_load() {
+        let mut sink = CsvSink::new(Vec::new());
+        sink.write(&result("10.0.0.1:443", Some(10))).unwrap();
+        let saved = sink.save_results();
+
+        let mut resumed = CsvSink::new(Vec::new());
+        resumed.load_results(saved).unwrap();
+        resumed.write(&result("10.0.0.1:443", None)).unwrap();
+        assert_eq!(
+            csv(&mut resumed),
+            "ip,port,state,sent,received,loss_percent,avg_latency_ms\n\
+             10.0.0.1,443,open,2,1,50.00,10.000\n"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! JSON Lines output
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::time::UNIX_EPOCH;
+
+use base64::Engine;
+use serde::Serialize;
+
+use crate::result::{ResultSink, ScanResult, ScanType, Service, State, TlsInfo};
+
+/// 每个目标一行 JSON 对象，包括没有匹配的目标，方便导入其他工具处理
+pub struct JsonlSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+// 一行输出的字段，没有值的字段省略
+#[derive(Serialize)]
+struct Record<'a> {
+    ip: IpAddr,
+    port: u16,
+    scan: ScanType,
+    round: u8,
+    state: State,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<f64>,
+    // 原始数据可能不是 UTF-8，用 base64 编码
+    #[serde(skip_serializing_if = "Option::is_none")]
+    banner: Option<String>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    rules: &'a [String],
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    captures: BTreeMap<&'a str, Option<&'a str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transfer: Option<TransferRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service: Option<&'a Service>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls: Option<&'a TlsInfo>,
+    matched: bool,
+    timestamp: f64, // Unix 时间，单位秒
+}
+
+#[derive(Serialize)]
+struct TransferRecord {
+    bytes: u64,
+    secs: f64,
+    bytes_per_sec: u64,
+}
+
+impl<'a> From<&'a ScanResult> for Record<'a> {
+    fn from(result: &'a ScanResult) -> Self {
+        Self {
+            ip: result.addr.ip(),
+            port: result.addr.port(),
+            scan: result.scan,
+            round: result.round,
+            state: result.state,
+            latency_ms: result.latency.map(|l| l.as_secs_f64() * 1000.0),
+            banner: result
+                .banner
+                .as_ref()
+                .map(|b| base64::engine::general_purpose::STANDARD.encode(b)),
+            rules: &result.rules,
+            captures: result
+                .captures
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_deref()))
+                .collect(),
+            transfer: result.transfer.map(|t| TransferRecord {
+                bytes: t.bytes,
+                secs: t.duration.as_secs_f64(),
+                bytes_per_sec: t.bytes_per_sec(),
+            }),
+            service: result.service.as_ref(),
+            tls: result.tls.as_ref(),
+            matched: result.matched,
+            timestamp: result
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64(),
+        }
+    }
+}
+
+impl<W: Write> ResultSink for JsonlSink<W> {
+    fn write(&mut self, result: &ScanResult) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, &Record::from(result))?;
+        writeln!(self.out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_jsonl_sink() {
+        let mut http = ScanResult::new("[::1]:8080".parse().unwrap(), ScanType::HttpHeaderMatch);
+        http.state = State::Open;
+        http.latency = Some(Duration::from_micros(1500));
+        http.banner = Some(b"HTTP/1.1 200 OK\r\n\xff".to_vec());
+        http.rules = vec!["Server: ^nginx".to_string()];
+        http.captures = vec![("CF-RAY".to_string(), None)];
+        http.matched = true;
+        http.time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let mut closed = ScanResult::new("10.0.0.1:22".parse().unwrap(), ScanType::SshVersion);
+        closed.round = 1;
+        closed.state = State::Closed;
+        closed.time = UNIX_EPOCH;
+
+        let mut sink = JsonlSink::new(Vec::new());
+        sink.write(&http).unwrap();
+        sink.write(&closed).unwrap();
+        sink.finish().unwrap();
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&sink.out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                serde_json::json!({
+                    "ip": "::1",
+                    "port": 8080,
+                    "scan": "http-header-match",
+                    "round": 0,
+                    "state": "open",
+                    "latency_ms": 1.5,
+                    "banner": "SFRUUC8xLjEgMjAwIE9LDQr/",
+                    "rules": ["Server: ^nginx"],
+                    "captures": {"CF-RAY": null},
+                    "matched": true,
+                    "timestamp": 1700000000.5,
+                }),
+                serde_json::json!({
+                    "ip": "10.0.0.1",
+                    "port": 22,
+                    "scan": "ssh-version",
+                    "round": 1,
+                    "state": "closed",
+                    "matched": false,
+                    "timestamp": 0.0,
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Masscan compatible list output
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::time::UNIX_EPOCH;
+
+use crate::result::{printable, ResultSink, ScanResult, State};
+
+/// 和 `masscan -oL` 相同的格式，每个开放的端口一行，有 banner 时再输出一行 banner
+///
+/// 这个格式可以用 `--target-file` 读回来作为下一次扫描的目标
+pub struct ListSink<W: Write> {
+    out: W,
+    started: bool,
+    // 已经输出过的端口，多轮扫描中每个端口只输出一次
+    reported: HashSet<SocketAddr>,
+}
+
+impl<W: Write> ListSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            started: false,
+            reported: HashSet::new(),
+        }
+    }
+}
+
+impl<W: Write> ResultSink for ListSink<W> {
+    fn write(&mut self, result: &ScanResult) -> io::Result<()> {
+        if !self.started {
+            writeln!(self.out, "#masscan")?;
+            self.started = true;
+        }
+        if result.state != State::Open || !self.reported.insert(result.addr) {
+            return Ok(());
+        }
+        let time = result
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let (ip, port) = (result.addr.ip(), result.addr.port());
+        writeln!(self.out, "open tcp {port} {ip} {time}")?;
+        if let Some((service, banner)) = result.service_banner() {
+            writeln!(
+                self.out,
+                "banner tcp {port} {ip} {time} {service} {}",
+                printable(banner)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.finish_interrupted()?;
+        writeln!(self.out, "# end")?;
+        self.out.flush()
+    }
+
+    // 继续扫描时追加到同一个文件，结尾要等扫描完成后才输出
+    fn finish_interrupted(&mut self) -> io::Result<()> {
+        if !self.started {
+            writeln!(self.out, "#masscan")?;
+            self.started = true;
+        }
+        self.out.flush()
+    }
+
+    // 检查点中保存已经输出过的端口，继续扫描时不再重复输出，文件开头也已经输出过了
+    fn save_results(&self) -> serde_json::Value {
+        serde_json::to_value(&self.reported).unwrap()
+    }
+
+    fn load_results(&mut self, results: serde_json::Value) -> serde_json::Result<()> {
+        if !results.is_null() {
+            self.reported = serde_json::from_value(results)?;
+            self.started = true;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::result::ScanType;
+
+    fn result(addr: &str, open: bool, banner: Option<&[u8]>) -> ScanResult {
+        let mut result = ScanResult::new(addr.parse().unwrap(), ScanType::SshVersion);
+        if open {
+            result.state = State::Open;
+        }
+        result.banner = banner.map(|b| b.to_vec());
+        result.time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        result
+    }
+
+    #[test]
+    fn test_list_sink() {
+        let mut sink = ListSink::new(Vec::new());
+        for r in [
+            result("10.0.0.1:22", true, Some(b"SSH-2.0-OpenSSH_8.4\r\n")),
+            result("10.0.0.2:22", false, None),
+            result("[2001:db8::1]:2222", true, None),
+            result("10.0.0.1:22", true, Some(b"SSH-2.0-OpenSSH_8.4\r\n")),
+        ] {
+            sink.write(&r).unwrap();
+        }
+        sink.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(sink.out).unwrap(),
+            "#masscan\n\
+             open tcp 22 10.0.0.1 1700000000\n\
+             banner tcp 22 10.0.0.1 1700000000 ssh SSH-2.0-OpenSSH_8.4\n\
+             open tcp 2222 2001:db8::1 1700000000\n\
+             # end\n"
+        );
+    }
+
+    #[test]
+    fn test_list_sink_resume() {
+        let mut sink = ListSink::new(Vec::new());
+        sink.write(&result("10.0.0.1:22", true, None)).unwrap();
+        sink.finish_interrupted().unwrap();
+        let saved = sink.save_results();
+
+        let mut resumed = ListSink::new(std::mem::take(&mut sink.out));
+        resumed.load_results(saved).unwrap();
+        for r in [
+            result("10.0.0.1:22", true, None),
+            result("10.0.0.2:22", true, None),
+        ] {
+            resumed.write(&r).unwrap();
+        }
+        resumed.finish().unwrap();
+        assert_eq!(
+            String::from_utf8(resumed.out).unwrap(),
+            "#masscan\n\
+             open tcp 22 10.0.0.1 1700000000\n\
+             open tcp 22 10.0.0.2 1700000000\n\
+             # end\n"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Human readable output
+
+use std::io::{self, Write};
+
+use indicatif::HumanBytes;
+
+use crate::result::{printable, ResultSink, ScanResult, ScanType};
+
+/// 供人阅读的文本格式，每个匹配的目标一行，各个扫描的格式不同
+pub struct TextSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TextSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+impl<W: Write> ResultSink for TextSink<W> {
+    fn write(&mut self, result: &ScanResult) -> io::Result<()> {
+        if !result.matched {
+            return Ok(());
+        }
+        match result.scan {
+            // banner 和响应可能是任意二进制数据，去掉结尾的换行后转义，输出在一行中
+            ScanType::Banner | ScanType::Probe | ScanType::SshVersion => {
+                let banner = result.banner.as_deref().unwrap_or_default();
+                write!(self.out, "{}\t{}", result.addr, printable(banner.trim_ascii_end()))?;
+            }
+            ScanType::HttpHeaderMatch => {
+                write!(self.out, "{}", result.addr)?;
+                for (_, value) in &result.captures {
+                    write!(self.out, "\t{}", value.as_deref().unwrap_or("-"))?;
+                }
+            }
+            ScanType::Service => {
+                let Some(service) = &result.service else {
+                    return Ok(());
+                };
+                write!(self.out, "{}\t{}", result.addr, service.name)?;
+                let description = service.description();
+                if !description.is_empty() {
+                    write!(self.out, "\t{}", description)?;
+                }
+                for cpe in &service.cpe {
+                    write!(self.out, "\t{}", cpe)?;
+                }
+            }
+            ScanType::SpeedTest => {
+                write!(self.out, "{}", result.addr)?;
+                if let Some(transfer) = &result.transfer {
+                    write!(
+                        self.out,
+                        "\t{} in {:.2}s\t{}/s",
+                        HumanBytes(transfer.bytes),
+                        transfer.duration.as_secs_f64(),
+                        HumanBytes(transfer.bytes_per_sec())
+                    )?;
+                }
+            }
+            // 每轮每个开放的目标一行，延迟在扫描结束后汇总成排名输出
+            ScanType::TcpConnect => write!(self.out, "{}\t{}", result.addr, result.state)?,
+            ScanType::Tls => {
+                let Some(tls) = &result.tls else {
+                    return Ok(());
+                };
+                write!(self.out, "{}\t{}", result.addr, tls.description())?;
+                if let Some(cert) = &tls.certificate {
+                    write!(
+                        self.out,
+                        "\t{}\t{}\t{}",
+                        cert.subject,
+                        cert.sans.join(","),
+                        cert.not_after
+                    )?;
+                }
+            }
+        }
+        writeln!(self.out)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::result::{Certificate, Service, State, TlsInfo, Transfer};
+
+    fn text(results: &[ScanResult]) -> String {
+        let mut sink = TextSink::new(Vec::new());
+        for result in results {
+            sink.write(result).unwrap();
+        }
+        sink.finish().unwrap();
+        String::from_utf8(sink.out).unwrap()
+    }
+
+    fn result(scan: ScanType) -> ScanResult {
+        let mut result = ScanResult::new("10.0.0.1:443".parse().unwrap(), scan);
+        result.state = State::Open;
+        result.matched = true;
+        result
+    }
+
+    #[test]
+    fn test_text_sink() {
+        let mut ssh = result(ScanType::SshVersion);
+        ssh.banner = Some(b"SSH-2.0-OpenSSH_8.4\r\n".to_vec());
+        let mut http = result(ScanType::HttpHeaderMatch);
+        http.captures = vec![("CF-RAY".to_string(), Some("SJC".to_string()))];
+        let mut http_missing = result(ScanType::HttpHeaderMatch);
+        http_missing.captures = vec![("CF-RAY".to_string(), None)];
+        let mut speed = result(ScanType::SpeedTest);
+        speed.transfer = Some(Transfer {
+            bytes: 2048,
+            duration: Duration::from_secs(2),
+        });
+        let mut unmatched = result(ScanType::SshVersion);
+        unmatched.matched = false;
+        let mut mysql = result(ScanType::Banner);
+        mysql.banner = Some(b"J\0\0\0\n8.0.36\0\x08\0\0\0".to_vec());
+        let mut smtp = result(ScanType::Banner);
+        smtp.banner = Some(b"220 mail.example.com ESMTP\r\n".to_vec());
+        let mut openssh = result(ScanType::Service);
+        openssh.service = Some(Service {
+            name: "ssh".to_string(),
+            product: Some("OpenSSH".to_string()),
+            version: Some("8.4p1 Debian 5".to_string()),
+            info: Some("protocol 2.0".to_string()),
+            cpe: vec!["cpe:/a:openbsd:openssh:8.4p1".to_string()],
+            ..Service::default()
+        });
+        let mut soft = result(ScanType::Service);
+        soft.service = Some(Service::named("ftp"));
+        let mut tls = result(ScanType::Tls);
+        tls.tls = Some(TlsInfo {
+            version: "TLSv1.2".to_string(),
+            cipher: Some("TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256".to_string()),
+            alpn: Some("h2".to_string()),
+            certificate: Some(Certificate {
+                subject: "CN=example.com".to_string(),
+                sans: vec!["DNS:example.com".to_string(), "DNS:www.example.com".to_string()],
+                not_after: "2025-01-01T00:00:00Z".to_string(),
+                ..Certificate::default()
+            }),
+            ..TlsInfo::default()
+        });
+
+        assert_eq!(
+            text(&[
+                ssh,
+                http,
+                http_missing,
+                speed,
+                unmatched,
+                mysql,
+                smtp,
+                openssh,
+                soft,
+                tls,
+                result(ScanType::TcpConnect)
+            ]),
+            "10.0.0.1:443\tSSH-2.0-OpenSSH_8.4\n\
+             10.0.0.1:443\tSJC\n\
+             10.0.0.1:443\t-\n\
+             10.0.0.1:443\t2.00 KiB in 2.00s\t1.00 KiB/s\n\
+             10.0.0.1:443\tJ\\x00\\x00\\x00\\x0A8.0.36\\x00\\x08\\x00\\x00\\x00\n\
+             10.0.0.1:443\t220 mail.example.com ESMTP\n\
+             10.0.0.1:443\tssh\tOpenSSH 8.4p1 Debian 5 (protocol 2.0)\tcpe:/a:openbsd:openssh:8.4p1\n\
+             10.0.0.1:443\tftp\n\
+             10.0.0.1:443\tTLSv1.2 TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256 (h2)\tCN=example.com\tDNS:example.com,DNS:www.example.com\t2025-01-01T00:00:00Z\n\
+             10.0.0.1:443\topen\n"
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Nmap compatible XML output
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bstr::ByteSlice;
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+
+use crate::result::{printable, ResultSink, ScanResult, ScanType, Service, State};
+
+/// 和 `nmap -oX` 相同结构的 XML，可以直接导入读取 nmap 结果的工具
+///
+/// 结果按 IP 分组成 `<host>`，所以在扫描结束后才输出，只输出至少有一个端口开放的 IP
+pub struct XmlSink<W: Write> {
+    out: W,
+    start: SystemTime,
+    hosts: BTreeMap<IpAddr, Host>,
+}
+
+// 一个 IP 所有端口在所有轮次中的结果
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Host {
+    start: u64, // 第一个和最后一个结果的 Unix 时间
+    end: u64,
+    ports: BTreeMap<u16, Port>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Port {
+    state: State,
+    service: Option<Service>,
+    // nmap 用脚本输出保存 banner 之类的信息，这里保存脚本名和输出
+    script: Option<(String, String)>,
+}
+
+impl<W: Write> XmlSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            start: SystemTime::now(),
+            hosts: BTreeMap::new(),
+        }
+    }
+
+    fn write_host(&mut self, ip: &IpAddr, host: &Host) -> io::Result<()> {
+        let addrtype = if ip.is_ipv4() { "ipv4" } else { "ipv6" };
+        writeln!(
+            self.out,
+            r#"<host starttime="{}" endtime="{}"><status state="up" reason="user-set" reason_ttl="0"/>"#,
+            host.start, host.end
+        )?;
+        writeln!(self.out, r#"<address addr="{ip}" addrtype="{addrtype}"/>"#)?;
+        writeln!(self.out, "<hostnames>\n</hostnames>\n<ports>")?;
+        for (port_id, port) in &host.ports {
+            // nmap 没有不可达状态，用 filtered 和不可达的原因表示
+            let (state, reason) = match port.state {
+                State::Open => ("open", "syn-ack"),
+                State::Closed => ("closed", "conn-refused"),
+                State::Filtered => ("filtered", "no-response"),
+                State::Unreachable => ("filtered", "host-unreach"),
+            };
+            write!(
+                self.out,
+                r#"<port protocol="tcp" portid="{port_id}"><state state="{state}" reason="{reason}" reason_ttl="0"/>"#
+            )?;
+            if let Some(service) = &port.service {
+                write!(self.out, r#"<service name="{}""#, escape(&service.name))?;
+                for (attr, value) in [
+                    ("product", &service.product),
+                    ("version", &service.version),
+                    ("extrainfo", &service.info),
+                    ("hostname", &service.hostname),
+                    ("ostype", &service.os),
+                    ("devicetype", &service.device),
+                ] {
+                    if let Some(value) = value {
+                        write!(self.out, r#" {attr}="{}""#, escape(value))?;
+                    }
+                }
+                write!(self.out, r#" method="probed" conf="10""#)?;
+                if service.cpe.is_empty() {
+                    write!(self.out, "/>")?;
+                } else {
+                    write!(self.out, ">")?;
+                    for cpe in &service.cpe {
+                        write!(self.out, "<cpe>{}</cpe>", escape(cpe))?;
+                    }
+                    write!(self.out, "</service>")?;
+                }
+            }
+            if let Some((id, output)) = &port.script {
+                write!(
+                    self.out,
+                    r#"<script id="{}" output="{}"/>"#,
+                    escape(id),
+                    escape(output)
+                )?;
+            }
+            writeln!(self.out, "</port>")?;
+        }
+        writeln!(self.out, "</ports>\n</host>")
+    }
+}
+
+impl<W: Write> ResultSink for XmlSink<W> {
+    fn write(&mut self, result: &ScanResult) -> io::Result<()> {
+        let time = unix_secs(result.time);
+        let host = self.hosts.entry(result.addr.ip()).or_insert_with(|| Host {
+            start: time,
+            end: time,
+            ports: BTreeMap::new(),
+        });
+        host.start = host.start.min(time);
+        host.end = host.end.max(time);
+
+        let port = host.ports.entry(result.addr.port()).or_insert(Port {
+            state: result.state,
+            service: None,
+            script: None,
+        });
+        port.state = port.state.merge(result.state);
+        // 只有收到服务端的响应时才能确定服务
+        if let Some(script) = script(result) {
+            let service = result.service.clone().unwrap_or_else(|| match result.scan {
+                ScanType::Tls => Service::named("ssl"),
+                _ => Service::named(result.service_banner().map_or("http", |(service, _)| service)),
+            });
+            port.service = Some(service);
+            port.script = Some(script);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let start = unix_secs(self.start);
+        let args: Vec<_> = std::env::args().collect();
+        let ports: BTreeSet<_> = self.hosts.values().flat_map(|h| h.ports.keys()).collect();
+        let services: Vec<_> = ports.iter().map(|p| p.to_string()).collect();
+        writeln!(
+            self.out,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE nmaprun>"
+        )?;
+        writeln!(
+            self.out,
+            r#"<nmaprun scanner="io_uring_scanner" args="{}" start="{start}" version="{}" xmloutputversion="1.05">"#,
+            escape(&args.join(" ")),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(
+            self.out,
+            r#"<scaninfo type="connect" protocol="tcp" numservices="{}" services="{}"/>"#,
+            services.len(),
+            services.join(",")
+        )?;
+
+        let hosts = std::mem::take(&mut self.hosts);
+        let mut up = 0;
+        for (ip, host) in &hosts {
+            if host.ports.values().any(|p| p.state == State::Open) {
+                self.write_host(ip, host)?;
+                up += 1;
+            }
+        }
+        self.hosts = hosts;
+
+        let end = SystemTime::now();
+        let elapsed = end.duration_since(self.start).unwrap_or_default();
+        writeln!(
+            self.out,
+            r#"<runstats><finished time="{}" elapsed="{:.2}" exit="success"/><hosts up="{up}" down="{}" total="{}"/>"#,
+            unix_secs(end),
+            elapsed.as_secs_f64(),
+            self.hosts.len() - up,
+            self.hosts.len()
+        )?;
+        writeln!(self.out, "</runstats>\n</nmaprun>")?;
+        self.out.flush()
+    }
+
+    // 结果按 IP 分组后才能输出，检查点中保存已经收到的结果
+    fn save_results(&self) -> serde_json::Value {
+        serde_json::to_value(&self.hosts).unwrap()
+    }
+
+    fn load_results(&mut self, results: serde_json::Value) -> serde_json::Result<()> {
+        if !results.is_null() {
+            self.hosts = serde_json::from_value(results)?;
+        }
+        Ok(())
+    }
+}
+
+// 各个扫描的服务信息，名称沿用 nmap 中输出同样内容的脚本
+fn script(result: &ScanResult) -> Option<(String, String)> {
+    match result.scan {
+        ScanType::HttpHeaderMatch => {
+            let (_, headers) = result.service_banner()?;
+            let output = headers
+                .lines()
+                .map(printable)
+                .collect::<Vec<_>>()
+                .join("\n");
+            Some(("http-headers".to_string(), output))
+        }
+        ScanType::SpeedTest => {
+            let transfer = result.transfer?;
+            Some((
+                "speed-test".to_string(),
+                format!(
+                    "{} in {:.2}s, {}/s",
+                    HumanBytes(transfer.bytes),
+                    transfer.duration.as_secs_f64(),
+                    HumanBytes(transfer.bytes_per_sec())
+                ),
+            ))
+        }
+        ScanType::Banner | ScanType::Probe | ScanType::Service | ScanType::SshVersion => {
+            let (_, banner) = result.service_banner()?;
+            Some(("banner".to_string(), printable(banner)))
+        }
+        ScanType::TcpConnect => None,
+        ScanType::Tls => {
+            let tls = result.tls.as_ref()?;
+            let mut lines = vec![tls.description()];
+            if let Some(cert) = &tls.certificate {
+                lines.push(format!("Subject: {}", cert.subject));
+                if !cert.sans.is_empty() {
+                    lines.push(format!("Subject Alternative Name: {}", cert.sans.join(", ")));
+                }
+                lines.push(format!("Issuer: {}", cert.issuer));
+                lines.push(format!("Public Key: {}", cert.key));
+                lines.push(format!("Not valid before: {}", cert.not_before));
+                lines.push(format!("Not valid after:  {}", cert.not_after));
+            }
+            Some(("tls-hello".to_string(), lines.join("\n")))
+        }
+    }
+}
+
+// 转义 XML 属性值中的特殊字符
+fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(&['&', '<', '>', '"', '\'', '\n'][..]) {
+        return Cow::Borrowed(s);
+    }
+    let mut escaped = String::with_capacity(s.len() + 16);
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#xa;"),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(addr: &str, scan: ScanType, open: bool, banner: Option<&[u8]>) -> ScanResult {
+        let mut result = ScanResult::new(addr.parse().unwrap(), scan);
+        if open {
+            result.state = State::Open;
+        }
+        result.banner = banner.map(|b| b.to_vec());
+        result.time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        result
+    }
+
+    fn hosts(sink: XmlSink<Vec<u8>>) -> String {
+        let xml = String::from_utf8(sink.out).unwrap();
+        let start = xml.find("<host ").unwrap();
+        let end = xml.find("<runstats>").unwrap();
+        xml[start..end].to_string()
+    }
+
+    #[test]
+    fn test_xml_sink() {
+        let mut sink = XmlSink::new(Vec::new());
+        for r in [
+            result("10.0.0.2:22", ScanType::SshVersion, false, None),
+            result(
+                "10.0.0.1:22",
+                ScanType::SshVersion,
+                true,
+                Some(b"SSH-2.0-OpenSSH_8.4 <\"&\">\r\n\xff"),
+            ),
+            result("10.0.0.1:2222", ScanType::SshVersion, false, None),
+        ] {
+            sink.write(&r).unwrap();
+        }
+        sink.finish().unwrap();
+        let xml = String::from_utf8(sink.out.clone()).unwrap();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE nmaprun>\n<nmaprun scanner=\"io_uring_scanner\""));
+        assert!(xml.contains(
+            r#"<scaninfo type="connect" protocol="tcp" numservices="2" services="22,2222"/>"#
+        ));
+        assert!(xml.contains(r#"<hosts up="1" down="1" total="2"/>"#));
+        assert!(xml.ends_with("</runstats>\n</nmaprun>\n"));
+        assert_eq!(
+            hosts(sink),
+            "<host starttime=\"1700000000\" endtime=\"1700000000\"><status state=\"up\" reason=\"user-set\" reason_ttl=\"0\"/>\n\
+             <address addr=\"10.0.0.1\" addrtype=\"ipv4\"/>\n\
+             <hostnames>\n</hostnames>\n<ports>\n\
+             <port protocol=\"tcp\" portid=\"22\"><state state=\"open\" reason=\"syn-ack\" reason_ttl=\"0\"/>\
+             <service name=\"ssh\" method=\"probed\" conf=\"10\"/>\
+             <script id=\"banner\" output=\"SSH-2.0-OpenSSH_8.4 &lt;&quot;&amp;&quot;&gt;\\x0D\\x0A\\xFF\"/></port>\n\
+             <port protocol=\"tcp\" portid=\"2222\"><state state=\"filtered\" reason=\"no-response\" reason_ttl=\"0\"/></port>\n\
+             </ports>\n</host>\n"
+        );
+    }
+
+    #[test]
+    fn test_xml_service() {
+        let mut sink = XmlSink::new(Vec::new());
+        let mut ssh = result(
+            "10.0.0.1:22",
+            ScanType::Service,
+            true,
+            Some(b"SSH-2.0-OpenSSH_8.4p1\r\n"),
+        );
+        ssh.service = Some(Service {
+            name: "ssh".to_string(),
+            product: Some("OpenSSH".to_string()),
+            version: Some("8.4p1".to_string()),
+            info: Some("protocol 2.0".to_string()),
+            cpe: vec!["cpe:/a:openbsd:openssh:8.4p1".to_string()],
+            ..Service::default()
+        });
+        let unknown = result("10.0.0.1:23", ScanType::Service, true, Some(b"\xff\xfd"));
+        sink.write(&ssh).unwrap();
+        sink.write(&unknown).unwrap();
+        sink.finish().unwrap();
+        let hosts = hosts(sink);
+        assert!(hosts.contains(
+            "<service name=\"ssh\" product=\"OpenSSH\" version=\"8.4p1\" extrainfo=\"protocol 2.0\" method=\"probed\" conf=\"10\">\
+             <cpe>cpe:/a:openbsd:openssh:8.4p1</cpe></service>"
+        ));
+        assert!(hosts.contains(
+            "<service name=\"unknown\" method=\"probed\" conf=\"10\"/><script id=\"banner\" output=\"\\xFF\\xFD\"/>"
+        ));
+    }
+
+    #[test]
+    fn test_xml_script() {
+        let http = result(
+            "10.0.0.1:80",
+            ScanType::HttpHeaderMatch,
+            true,
+            Some(b"HTTP/1.1 200 OK\r\nServer: nginx\r\n\r\nbody"),
+        );
+        assert_eq!(
+            script(&http),
+            Some((
+                "http-headers".to_string(),
+                "HTTP/1.1 200 OK\nServer: nginx".to_string()
+            ))
+        );
+        assert_eq!(escape("a\n<b>"), "a&#xa;&lt;b&gt;");
+        assert_eq!(
+            script(&result("10.0.0.1:21", ScanType::Service, true, Some(b"220 FTP\r\n"))),
+            Some(("banner".to_string(), "220 FTP".to_string()))
+        );
+        assert_eq!(
+            script(&result("10.0.0.1:80", ScanType::TcpConnect, true, None)),
+            None
+        );
+    }
+}
@@ -6,15 +6,15 @@ use std::rc::Rc;
 
 use io_uring::Submitter;
 pub use nix::libc::iovec;
-use nix::sys::socket::SockaddrIn;
+use nix::sys::socket::SockaddrStorage;
 
 pub type EntryIdx = u64;
 
 #[derive(Clone)]
 pub struct EntryInfo {
     // 在这里，使用引用计数的目的是为了避免在多个 entry 中存储相同的 IP 地址时出现内存浪费或重复创建的情况。
-    // 通过使用引用计数，多个 entry 或其他对象可以共享同一个 SockaddrIn 实例，并在所有者数量为 0 时正确地将其释放。
-    pub ip: Rc<SockaddrIn>, // 使用引用计数来持有 SockaddrIn 类型的 IP 地址
+    // 通过使用引用计数，多个 entry 或其他对象可以共享同一个 SockaddrStorage 实例，并在所有者数量为 0 时正确地将其释放。
+    pub ip: Rc<SockaddrStorage>, // 使用引用计数来持有 IP 地址，IPv4 和 IPv6 都用 SockaddrStorage 表示
     pub step: u8,           // 记录 I/O 操作执行的步骤
     pub buf: Option<BufferInfo>, // 缓冲信息
     pub fd: RawFd,          // 文件描述符
@@ -122,6 +122,14 @@ impl RingAllocator {
         self.entries.capacity() - self.free_entry_idx.len()
     }
 
+    // 获取未使用的缓冲区数量
+    pub fn free_buf_count(&self, direction: BufferDirection) -> usize {
+        match direction {
+            BufferDirection::RX => self.free_rx_buf_idx.len(),
+            BufferDirection::TX => self.free_tx_buf_idx.len(),
+        }
+    }
+
     // 释放指定的 entry，并且清除该 entry 中对应的缓冲区
     pub fn free_entry(&mut self, idx: EntryIdx) {
         if let Some(buf) = &self.entries[idx as usize].as_ref().unwrap().buf {
@@ -201,7 +209,7 @@ mod tests {
 
     use super::*;
     use io_uring::{IoUring, Submitter};
-    use nix::sys::socket::SockaddrIn;
+    use nix::sys::socket::SockaddrStorage;
     use std::ffi::c_void;
     use std::os::unix::io::RawFd;
     use std::rc::Rc;
@@ -245,7 +253,9 @@ mod tests {
         let (mut allocator, _) = test_default(None, None, None);
 
         let entry_info = EntryInfo {
-            ip: Rc::new(SockaddrIn::new(127, 0, 0, 1, 0)),
+            ip: Rc::new(SockaddrStorage::from(
+                "127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap(),
+            )),
             step: 0,
             buf: None,
             fd: -1,
@@ -274,7 +284,9 @@ mod tests {
         let (mut allocator, _) = test_default(None, None, None);
 
         let entry_info = EntryInfo {
-            ip: Rc::new(SockaddrIn::new(127, 0, 0, 1, 0)),
+            ip: Rc::new(SockaddrStorage::from(
+                "127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap(),
+            )),
             step: 0,
             buf: None,
             fd: -1,
@@ -294,7 +306,9 @@ mod tests {
         let (mut allocator, _) = test_default(Some(ring_size), None, None);
 
         let entry_info = EntryInfo {
-            ip: Rc::new(SockaddrIn::new(127, 0, 0, 1, 0)),
+            ip: Rc::new(SockaddrStorage::from(
+                "127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap(),
+            )),
             step: 0,
             buf: None,
             fd: -1,
@@ -314,7 +328,9 @@ mod tests {
         let (mut allocator, _) = test_default(Some(ring_size), None, None);
 
         let entry_info = EntryInfo {
-            ip: Rc::new(SockaddrIn::new(127, 0, 0, 1, 0)),
+            ip: Rc::new(SockaddrStorage::from(
+                "127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap(),
+            )),
             step: 0,
             buf: None,
             fd: -1,
@@ -1,5 +1,8 @@
 //! Scan type specific logic
 
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::os::unix::io::RawFd;
 
 use io_uring::{
@@ -8,13 +11,18 @@ use io_uring::{
     types::Timespec,  // Timespec 的类型定义
     Probe,  // io_uring 支持检测
 };
-use nix::sys::socket::SockaddrIn;  // 套接字地址类型
+use nix::sys::socket::{AddressFamily, SockaddrStorage};  // 套接字地址类型，同时支持 IPv4 和 IPv6
 
+use crate::result::ScanResult;
 use crate::ring::{EntryInfo, RingAllocator};  // 自定义的引用类型
 
+pub mod banner;
 pub mod http_header_match;
-pub mod ssh_version;
+pub mod probe;
+pub mod service;
+pub mod speed_test;
 pub mod tcp_connect;
+pub mod tls;
 
 /// 超时时间的结构体，用于连接、读取和写入
 pub struct Timeouts {
@@ -34,26 +42,81 @@ pub trait Scan {
     /// 返回需要扫描单个 IP 所需的 io_uring 操作次数
     fn ops_per_ip(&self) -> usize;
 
-    /// 处理已完成的 io_uring 操作，返回是否完成了整个 IP 的扫描
+    /// 返回操作步骤的名称，用于统计汇总
+    fn step_name(&self, step: u8) -> String;
+
+    /// 处理已完成的 io_uring 操作，完成了整个目标的扫描时返回它的结果
     fn process_completed_entry(
         &mut self,
         cq_entry: &cqueue::Entry,
         entry_info: &EntryInfo,
         ring_allocator: &RingAllocator,
-    ) -> bool;
+    ) -> Option<ScanResult>;
 
     /// 推入 io_uring 操作以扫描对等 IP
     fn push_scan_ops(
         &mut self,
         sckt: RawFd,
-        ip: &SockaddrIn,
+        ip: &SockaddrStorage,
         squeue: &mut SubmissionQueue,
         allocator: &mut RingAllocator,
         timeouts: &Timeouts,
     ) -> Result<usize, PushError>;
 
-    /// 创建用于此扫描的套接字
-    fn socket(&self) -> RawFd;
+    /// 创建用于此扫描的套接字，地址族与目标地址一致
+    fn socket(&self, family: AddressFamily) -> RawFd;
+
+    /// 推入处理完成事件时产生的后续 io_uring 操作（例如继续读取），返回推入的操作数
+    fn push_followup_ops(
+        &mut self,
+        _squeue: &mut SubmissionQueue,
+        _allocator: &mut RingAllocator,
+        _timeouts: &Timeouts,
+    ) -> usize {
+        0
+    }
+
+    /// 全部轮次扫描结束后调用，把汇总结果写到 `out`
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// 返回保存到检查点的累积结果，扫描过程中直接输出结果的扫描不需要保存
+    fn save_results(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// 从检查点恢复累积的结果
+    fn load_results(&mut self, _results: serde_json::Value) -> serde_json::Result<()> {
+        Ok(())
+    }
+}
+
+/// 正在扫描的目标的结果，按套接字保存，在目标的各个步骤完成时逐步填充
+///
+/// 套接字关闭后，描述符可能在处理关闭的完成事件之前就被新的目标复用，所以每个描述符对应一个队列，
+/// 完成事件总是属于队列中最早的目标
+#[derive(Default)]
+pub struct PendingResults(HashMap<RawFd, VecDeque<ScanResult>>);
+
+impl PendingResults {
+    pub fn insert(&mut self, fd: RawFd, result: ScanResult) {
+        self.0.entry(fd).or_default().push_back(result);
+    }
+
+    pub fn get_mut(&mut self, fd: RawFd) -> Option<&mut ScanResult> {
+        self.0.get_mut(&fd).and_then(|queue| queue.front_mut())
+    }
+
+    // 目标扫描完成，取出它的结果
+    pub fn remove(&mut self, fd: RawFd) -> Option<ScanResult> {
+        let queue = self.0.get_mut(&fd)?;
+        let result = queue.pop_front();
+        if queue.is_empty() {
+            self.0.remove(&fd);
+        }
+        result
+    }
 }
 
 /// 检查操作是否被支持，如果不支持则产生 panic
@@ -65,6 +128,25 @@ fn check_op_supported(probe: &Probe, opcode: u8, name: &str) ->bool {
     return result;
 }
 
+/// 将 io_uring 使用的套接字地址转换为标准库的 SocketAddr，用于输出结果
+pub fn socket_addr(addr: &SockaddrStorage) -> SocketAddr {
+    if let Some(sin) = addr.as_sockaddr_in() {
+        SocketAddrV4::from(*sin).into()
+    } else if let Some(sin6) = addr.as_sockaddr_in6() {
+        SocketAddrV6::from(*sin6).into()
+    } else {
+        unreachable!()
+    }
+}
+
+/// 格式化后最长的地址，用于计算需要预先分配的发送缓冲区大小
+fn longest_addr() -> SockaddrStorage {
+    SockaddrStorage::from(SocketAddr::new(
+        IpAddr::V6(Ipv6Addr::from(u128::MAX)),
+        u16::MAX,
+    ))
+}
+
 /// 判断是否可以推入 io_uring 操作以扫描指定的 IP
 pub fn can_push(squeue: &SubmissionQueue, scan: &dyn Scan, allocator: &RingAllocator) -> bool {
     let ops_per_ip = scan.ops_per_ip();
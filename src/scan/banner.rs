@@ -1,10 +1,12 @@
-//! SSH scan to grab server version
+//! Banner grab scan: connect and record the first bytes the server sends, e.g. the SSH version
 
-use std::net::Ipv4Addr;
 use std::rc::Rc;
 
-use bstr::ByteSlice;
-use io_uring::{cqueue, opcode, squeue, types::Fd, Probe};
+use io_uring::{
+    cqueue, opcode, squeue,
+    types::{Fd, Timespec},
+    Probe,
+};
 use nix::{
     errno::Errno,
     libc,
@@ -12,12 +14,22 @@ use nix::{
     unistd,
 };
 
-use crate::config::SshVersionScanOptions;
+use crate::result::{ScanResult, ScanType, State};
 use crate::ring::{BufferDirection, BufferInfo, EntryInfo, RingAllocator};
-use crate::scan::{check_op_supported, PushError, RawFd, Scan, SockaddrIn, Timeouts};
+use crate::scan::{
+    check_op_supported, socket_addr, PendingResults, PushError, RawFd, Scan, SockaddrStorage,
+    Timeouts,
+};
 
-pub struct ScanSshVersion {
-    opts: SshVersionScanOptions,
+pub struct ScanBanner {
+    /// Scan type reported in the results
+    scan: ScanType,
+    /// Regex the banner must match, all banners match if None
+    regex: Option<regex::bytes::Regex>,
+    /// Recv timeout overriding the global read timeout
+    wait: Option<Timespec>,
+    /// Results of in progress targets
+    results: PendingResults,
 }
 
 /// Describes what scan step does an entry do
@@ -43,22 +55,34 @@ impl From<u8> for EntryStep {
     }
 }
 
-impl ScanSshVersion {
-    /// Parse response and log match
-    fn handle_response(&self, addr: &SockaddrIn, buf: &[u8]) {
-        if self.opts.regex.as_ref().map_or(true, |r| r.is_match(buf)) {
-            println!("{} {:?}", Ipv4Addr::from(addr.ip()), buf.as_bstr());
+impl ScanBanner {
+    /// Record server banner and whether it matches
+    fn handle_response(&mut self, fd: RawFd, buf: &[u8]) {
+        let matched = self.regex.as_ref().map_or(true, |r| r.is_match(buf));
+        if let Some(result) = self.results.get_mut(fd) {
+            result.banner = Some(buf.to_vec());
+            result.matched = matched;
+            if let (true, Some(regex)) = (matched, self.regex.as_ref()) {
+                result.rules = vec![regex.to_string()];
+            }
         }
     }
 
-    pub fn new(opts: &SshVersionScanOptions) -> Self {
+    pub fn new(scan: ScanType, regex: Option<&regex::bytes::Regex>, wait_ms: Option<u64>) -> Self {
         Self {
-            opts: opts.to_owned(),
+            scan,
+            regex: regex.cloned(),
+            wait: wait_ms.map(|ms| {
+                Timespec::new()
+                    .sec(ms / 1000)
+                    .nsec((ms % 1000) as u32 * 1_000_000)
+            }),
+            results: PendingResults::default(),
         }
     }
 }
 
-impl Scan for ScanSshVersion {
+impl Scan for ScanBanner {
     fn check_supported(&self, probe: &Probe) -> bool {
         check_op_supported(probe, opcode::Connect::CODE, "connect") &&
         check_op_supported(probe, opcode::LinkTimeout::CODE, "link timeout") &&
@@ -74,12 +98,16 @@ impl Scan for ScanSshVersion {
         5
     }
 
+    fn step_name(&self, step: u8) -> String {
+        format!("{:?}", EntryStep::from(step))
+    }
+
     fn process_completed_entry(
         &mut self,
         cq_entry: &cqueue::Entry,
         entry_info: &EntryInfo,
         ring_allocator: &RingAllocator,
-    ) -> bool {
+    ) -> Option<ScanResult> {
         let step = EntryStep::from(entry_info.step);
         let errno = Errno::from_i32(-cq_entry.result());
         log::debug!(
@@ -97,34 +125,45 @@ impl Scan for ScanSshVersion {
             );
         }
         match step {
+            EntryStep::Connect => {
+                if let Some(result) = self.results.get_mut(entry_info.fd) {
+                    result.state = State::from_connect(cq_entry.result());
+                    if result.state == State::Open {
+                        result.latency = Some(entry_info.start.elapsed());
+                    }
+                }
+                None
+            }
             EntryStep::Recv => {
                 let ret = cq_entry.result();
                 if ret > 0 {
                     let buf = ring_allocator.get_buf(entry_info.buf.as_ref().unwrap().idx);
-                    self.handle_response(&entry_info.ip, &buf[..ret as usize]);
+                    self.handle_response(entry_info.fd, &buf[..ret as usize]);
                 }
-                false
+                None
             }
             EntryStep::Close => {
                 if cq_entry.result() == -libc::ECANCELED {
                     // if a previous entry errored and the socket close was canceled, do it now to avoid fd leak
                     unistd::close(entry_info.fd).unwrap();
                 }
-                true
+                self.results.remove(entry_info.fd)
             }
-            _ => false,
+            _ => None,
         }
     }
 
     fn push_scan_ops(
         &mut self,
         sckt: RawFd,
-        addr: &SockaddrIn,
+        addr: &SockaddrStorage,
         squeue: &mut io_uring::squeue::SubmissionQueue,
         allocator: &mut RingAllocator,
         timeouts: &Timeouts,
     ) -> Result<usize, PushError> {
         let addr = Rc::new(addr.to_owned());
+        self.results
+            .insert(sckt, ScanResult::new(socket_addr(&addr), self.scan));
 
         let entry_connect_idx = allocator
             .alloc_entry(EntryInfo {
@@ -186,7 +225,7 @@ impl Scan for ScanSshVersion {
                 start: std::time::Instant::now(),
             })
             .unwrap();
-        let op_recv_timeout = opcode::LinkTimeout::new(&timeouts.read)
+        let op_recv_timeout = opcode::LinkTimeout::new(self.wait.as_ref().unwrap_or(&timeouts.read))
             .build()
             .flags(squeue::Flags::IO_LINK)
             .user_data(entry_recv_timeout_idx);
@@ -217,9 +256,9 @@ impl Scan for ScanSshVersion {
         Ok(ops.len())
     }
 
-    fn socket(&self) -> RawFd {
+    fn socket(&self, family: AddressFamily) -> RawFd {
         socket(
-            AddressFamily::Inet,
+            family,
             SockType::Stream,
             SockFlag::empty(),
             None,
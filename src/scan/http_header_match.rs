@@ -1,8 +1,12 @@
 //! HTTP scan to match by response headers
 
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Write;
-use std::net::Ipv4Addr;
+use std::io::{self, Read, Write as _};
+use std::net::SocketAddr;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
 
 use bstr::ByteSlice;
 use io_uring::{cqueue, opcode, squeue, types::Fd, Probe};
@@ -12,14 +16,147 @@ use nix::{
     sys::socket::{socket, AddressFamily, SockFlag, SockType, SockaddrLike},
     unistd,
 };
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme};
 
 use crate::config::HttpHeaderMatchScanOptions;
+use crate::result::{ScanResult, ScanType, State};
 use crate::ring::{BufferDirection, BufferInfo, EntryInfo, RingAllocator};
-use crate::scan::{check_op_supported, PushError, RawFd, Scan, SockaddrIn, Timeouts};
+use crate::scan::{
+    check_op_supported, longest_addr, socket_addr, PendingResults, PushError, RawFd, Scan,
+    SockaddrStorage, SubmissionQueue, Timeouts,
+};
+
+/// Room in the TX buffers for the TLS handshake records sent before and along with the request
+const TLS_TX_OVERHEAD: usize = 2048;
+
+/// Stop reading a TLS response after this many decrypted bytes, if the headers have not ended
+const MAX_TLS_RESPONSE_SIZE: usize = 64 * 1024;
 
 pub struct ScanHttpHeaderMatch {
     opts: HttpHeaderMatchScanOptions,
     tx_buf_size: Option<usize>,
+    /// Captured header value of matching IPs, the first one seen over all rounds
+    captured: HashMap<SocketAddr, Option<String>>,
+    /// Results of in progress targets
+    results: PendingResults,
+    /// TLS client configuration, set with --tls
+    tls_config: Option<Arc<ClientConfig>>,
+    /// In progress TLS connections, by socket
+    tls_targets: HashMap<RawFd, TlsTarget>,
+    /// TLS sockets that need their next exchange to be pushed
+    pending_tls: Vec<RawFd>,
+}
+
+/// State of a target scanned over TLS
+///
+/// The TLS connection runs in memory, its records are sent and received with fixed buffer ops,
+/// one exchange at a time, until the response headers have been decrypted
+struct TlsTarget {
+    addr: Rc<SockaddrStorage>,
+    conn: ClientConnection,
+    /// TLS records not sent yet
+    outgoing: Vec<u8>,
+    /// Decrypted response
+    response: Vec<u8>,
+    /// Set when the server closed the TLS session
+    closed: bool,
+}
+
+impl TlsTarget {
+    /// Start a connection, the request is sent by the connection once the handshake is done
+    fn new(
+        addr: Rc<SockaddrStorage>,
+        config: &Arc<ClientConfig>,
+        server_name: ServerName<'static>,
+        request: &[u8],
+    ) -> Self {
+        let mut conn = ClientConnection::new(Arc::clone(config), server_name)
+            .expect("Failed to create TLS connection");
+        conn.writer().write_all(request).unwrap();
+        let mut target = Self {
+            addr,
+            conn,
+            outgoing: Vec::new(),
+            response: Vec::new(),
+            closed: false,
+        };
+        target.flush();
+        target
+    }
+
+    /// Move the records the connection has to send to the outgoing buffer
+    fn flush(&mut self) {
+        while self.conn.wants_write() {
+            // writing to a Vec never fails
+            self.conn.write_tls(&mut self.outgoing).unwrap();
+        }
+    }
+
+    /// Process records received from the server, then decrypt the response and queue the records to send
+    fn process(&mut self, mut records: &[u8]) -> io::Result<()> {
+        while !records.is_empty() {
+            self.conn.read_tls(&mut records)?;
+            self.conn
+                .process_new_packets()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        match self.conn.reader().read_to_end(&mut self.response) {
+            Ok(_) => self.closed = true,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        self.flush();
+        Ok(())
+    }
+
+    /// Whether there is nothing more to read
+    fn is_done(&self) -> bool {
+        self.closed
+            || self.response.len() >= MAX_TLS_RESPONSE_SIZE
+            || self.response.find(b"\r\n\r\n").is_some()
+    }
+}
+
+/// Accepts any certificate, the scan looks at servers, it does not need to trust them
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
 }
 
 /// Describes what scan step does an entry do
@@ -50,12 +187,13 @@ impl From<u8> for EntryStep {
 }
 
 impl ScanHttpHeaderMatch {
-    /// Parse response headers and log match
-    fn handle_response(&self, addr: &SockaddrIn, buf: &[u8]) {
+    /// Parse response headers, return whether all rules matched, and the captured header value if any
+    fn parse_response<'a>(&self, buf: &'a [u8]) -> (bool, Option<&'a [u8]>) {
         // The parsing here never copies data from the response buffer
         // We also usr bstr to operate directly on &[u8] instead of &str which would require valid UTF-8
         // See https://www.rfc-editor.org/rfc/rfc2616.html#section-4.2
         let mut match_count = 0;
+        let mut captured = None;
         for line in buf.lines() {
             if line.is_empty() {
                 // double crlf, end of headers, bail out
@@ -72,10 +210,45 @@ impl ScanHttpHeaderMatch {
                         match_count += 1;
                     }
                 }
+                if let Some(rule) = self.opts.capture_header.as_ref() {
+                    // header names are case insensitive, and proxies often lowercase them
+                    if captured.is_none() && rule.key.as_bytes().eq_ignore_ascii_case(hdr_key) {
+                        captured = rule
+                            .val_regex
+                            .captures(hdr_value)
+                            .and_then(|c| c.get(1).or_else(|| c.get(0)).map(|m| m.as_bytes()));
+                    }
+                }
             }
         }
-        if match_count == self.opts.response_header_regexs.len() {
-            println!("{}", Ipv4Addr::from(addr.ip()));
+        (
+            match_count == self.opts.response_header_regexs.len(),
+            captured,
+        )
+    }
+
+    /// Parse response headers and record match
+    fn handle_response(&mut self, fd: RawFd, buf: &[u8]) {
+        let (matched, captured) = self.parse_response(buf);
+        let captured = captured.map(|c| c.to_str_lossy().into_owned());
+        let Some(result) = self.results.get_mut(fd) else {
+            return;
+        };
+        result.banner = Some(buf.to_vec());
+        result.matched = matched;
+        if matched {
+            result.rules = self
+                .opts
+                .response_header_regexs
+                .iter()
+                .map(|rule| rule.to_string())
+                .collect();
+        }
+        if let Some(rule) = self.opts.capture_header.as_ref() {
+            result.captures = vec![(rule.key.clone(), captured.clone())];
+            if matched {
+                self.captured.entry(result.addr).or_insert(captured);
+            }
         }
     }
 
@@ -83,9 +256,150 @@ impl ScanHttpHeaderMatch {
         Self {
             opts: opts.to_owned(),
             tx_buf_size: None,
+            captured: HashMap::new(),
+            results: PendingResults::default(),
+            tls_config: opts.tls.then(Self::tls_config),
+            tls_targets: HashMap::new(),
+            pending_tls: Vec::new(),
         }
     }
 
+    fn tls_config() -> Arc<ClientConfig> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .expect("Failed to configure TLS versions")
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Arc::new(config)
+    }
+
+    /// Feed the records received from a TLS server to its connection, report the response once complete
+    fn handle_tls_records(&mut self, fd: RawFd, records: &[u8]) -> Option<ScanResult> {
+        let target = self.tls_targets.get_mut(&fd)?;
+        if let Err(e) = target.process(records) {
+            log::debug!("TLS error with {}: {}", target.addr, e);
+            return self.finish_tls_target(fd);
+        }
+        if target.is_done() {
+            return self.finish_tls_target(fd);
+        }
+        self.pending_tls.push(fd);
+        None
+    }
+
+    /// Close socket, and match the decrypted response
+    fn finish_tls_target(&mut self, fd: RawFd) -> Option<ScanResult> {
+        let target = self.tls_targets.remove(&fd)?;
+        unistd::close(fd).unwrap();
+        if !target.response.is_empty() {
+            self.handle_response(fd, &target.response);
+        }
+        self.results.remove(fd)
+    }
+
+    /// Build the ops sending the pending TLS records if any, then reading the server reply
+    fn tls_exchange_ops(
+        &self,
+        sckt: RawFd,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> Vec<squeue::Entry> {
+        let target = &self.tls_targets[&sckt];
+        let mut ops = Vec::with_capacity(4);
+
+        if !target.outgoing.is_empty() {
+            // TX buffers have room for the request and the handshake records,
+            // anything left after a short write is sent with the next exchange
+            let len = target.outgoing.len().min(self.tx_buf_size.unwrap());
+            let tx_buffer =
+                allocator.alloc_buf(BufferDirection::TX, Some(&target.outgoing[..len]));
+            let op_send_idx = allocator
+                .alloc_entry(EntryInfo {
+                    ip: Rc::clone(&target.addr),
+                    step: EntryStep::Send as u8,
+                    buf: Some(BufferInfo {
+                        idx: tx_buffer.idx,
+                        direction: BufferDirection::TX,
+                    }),
+                    fd: sckt,
+                    start: Instant::now(),
+                })
+                .unwrap();
+            ops.push(
+                opcode::WriteFixed::new(
+                    Fd(sckt),
+                    tx_buffer.iov.iov_base.cast::<u8>(),
+                    len as u32,
+                    tx_buffer.idx as u16,
+                )
+                .build()
+                .flags(squeue::Flags::IO_LINK)
+                .user_data(op_send_idx),
+            );
+
+            let entry_send_timeout_idx = allocator
+                .alloc_entry(EntryInfo {
+                    ip: Rc::clone(&target.addr),
+                    step: EntryStep::SendTimeout as u8,
+                    buf: None,
+                    fd: sckt,
+                    start: Instant::now(),
+                })
+                .unwrap();
+            ops.push(
+                opcode::LinkTimeout::new(&timeouts.write)
+                    .build()
+                    .flags(squeue::Flags::IO_LINK)
+                    .user_data(entry_send_timeout_idx),
+            );
+        }
+
+        let rx_buffer = allocator.alloc_buf(BufferDirection::RX, None);
+        let op_recv_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&target.addr),
+                step: EntryStep::Recv as u8,
+                buf: Some(BufferInfo {
+                    idx: rx_buffer.idx,
+                    direction: BufferDirection::RX,
+                }),
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        ops.push(
+            opcode::ReadFixed::new(
+                Fd(sckt),
+                rx_buffer.iov.iov_base.cast::<u8>(),
+                rx_buffer.iov.iov_len as u32,
+                rx_buffer.idx as u16,
+            )
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(op_recv_idx),
+        );
+
+        let entry_recv_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&target.addr),
+                step: EntryStep::RecvTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        ops.push(
+            opcode::LinkTimeout::new(&timeouts.read)
+                .build()
+                .user_data(entry_recv_timeout_idx),
+        );
+
+        ops
+    }
+
     fn parse_header_line(line: &[u8]) -> Option<(&[u8], &[u8])> {
         if let Some((key, value)) = line.split_once_str(":") {
             let key = key.trim_ascii_end();
@@ -96,7 +410,7 @@ impl ScanHttpHeaderMatch {
         }
     }
 
-    fn format_request(&self, addr: &SockaddrIn) -> String {
+    fn format_request(&self, addr: &SockaddrStorage) -> String {
         let mut s = if let Some(size_hint) = self.tx_buf_size {
             String::with_capacity(size_hint)
         } else {
@@ -104,10 +418,15 @@ impl ScanHttpHeaderMatch {
         };
         write!(
             &mut s,
-            "{} {} HTTP/1.1\r\nHost: {}\r\n",
-            self.opts.request_verb, self.opts.request_uri, addr,
+            "{} {} HTTP/1.1\r\nHost: ",
+            self.opts.request_verb, self.opts.request_uri,
         )
         .unwrap();
+        match &self.opts.request_host {
+            Some(host) => write!(&mut s, "{}", host).unwrap(),
+            None => write!(&mut s, "{}", addr).unwrap(),
+        }
+        write!(&mut s, "\r\n").unwrap();
         for hdr in &self.opts.request_headers {
             write!(&mut s, "{}: {}\r\n", hdr.key, hdr.val).unwrap();
         }
@@ -126,9 +445,12 @@ impl Scan for ScanHttpHeaderMatch {
     }
 
     fn max_tx_size(&mut self) -> Option<usize> {
-        let sz = self
-            .format_request(&SockaddrIn::new(255, 255, 255, 255, u16::MAX))
+        let mut sz = self
+            .format_request(&longest_addr())
             .len();
+        if self.tls_config.is_some() {
+            sz += TLS_TX_OVERHEAD;
+        }
         self.tx_buf_size = Some(sz);
         Some(sz)
     }
@@ -137,12 +459,16 @@ impl Scan for ScanHttpHeaderMatch {
         7
     }
 
+    fn step_name(&self, step: u8) -> String {
+        format!("{:?}", EntryStep::from(step))
+    }
+
     fn process_completed_entry(
         &mut self,
         cq_entry: &cqueue::Entry,
         entry_info: &EntryInfo,
         ring_allocator: &RingAllocator,
-    ) -> bool {
+    ) -> Option<ScanResult> {
         let step = EntryStep::from(entry_info.step);
         let errno = Errno::from_i32(-cq_entry.result());
         log::debug!(
@@ -160,35 +486,63 @@ impl Scan for ScanHttpHeaderMatch {
             );
         }
         match step {
+            EntryStep::Connect => {
+                if let Some(result) = self.results.get_mut(entry_info.fd) {
+                    result.state = State::from_connect(cq_entry.result());
+                    if result.state == State::Open {
+                        result.latency = Some(entry_info.start.elapsed());
+                    }
+                }
+                None
+            }
+            EntryStep::Send => {
+                let ret = cq_entry.result();
+                if let (Some(target), true) = (self.tls_targets.get_mut(&entry_info.fd), ret > 0) {
+                    target.outgoing.drain(..ret as usize);
+                }
+                None
+            }
+            EntryStep::Recv if self.tls_config.is_some() => {
+                let ret = cq_entry.result();
+                if ret <= 0 {
+                    // EOF, error, timeout, or canceled because a previous step failed
+                    return self.finish_tls_target(entry_info.fd);
+                }
+                let buf = ring_allocator.get_buf(entry_info.buf.as_ref().unwrap().idx);
+                self.handle_tls_records(entry_info.fd, &buf[..ret as usize])
+            }
             EntryStep::Recv => {
-                if cq_entry.result() > 0 {
-                    self.handle_response(
-                        &entry_info.ip,
-                        ring_allocator.get_buf(entry_info.buf.as_ref().unwrap().idx),
-                    );
+                let ret = cq_entry.result();
+                if ret > 0 {
+                    let buf = ring_allocator.get_buf(entry_info.buf.as_ref().unwrap().idx);
+                    self.handle_response(entry_info.fd, &buf[..ret as usize]);
                 }
-                false
+                None
             }
             EntryStep::Close => {
                 if cq_entry.result() == -libc::ECANCELED {
                     // if a previous entry errored and the socket close was canceled, do it now to avoid fd leak
                     unistd::close(entry_info.fd).unwrap();
                 }
-                true
+                self.results.remove(entry_info.fd)
             }
-            _ => false,
+            _ => None,
         }
     }
 
     fn push_scan_ops(
         &mut self,
         sckt: RawFd,
-        addr: &SockaddrIn,
+        addr: &SockaddrStorage,
         squeue: &mut io_uring::squeue::SubmissionQueue,
         allocator: &mut RingAllocator,
         timeouts: &Timeouts,
     ) -> Result<usize, PushError> {
         let addr = Rc::new(addr.to_owned());
+        self.results.insert(
+            sckt,
+            ScanResult::new(socket_addr(&addr), ScanType::HttpHeaderMatch),
+        );
 
         let entry_connect_idx = allocator
             .alloc_entry(EntryInfo {
@@ -219,6 +573,23 @@ impl Scan for ScanHttpHeaderMatch {
             .user_data(entry_connect_timeout_idx);
 
         let req = self.format_request(&addr);
+        if let Some(config) = &self.tls_config {
+            // Without SNI the server name is only used to look up resumable sessions
+            let server_name = self
+                .opts
+                .sni
+                .clone()
+                .unwrap_or_else(|| ServerName::from(socket_addr(&addr).ip()));
+            let target = TlsTarget::new(Rc::clone(&addr), config, server_name, req.as_bytes());
+            self.tls_targets.insert(sckt, target);
+            let mut ops = vec![op_connect, op_connect_timeout];
+            ops.extend(self.tls_exchange_ops(sckt, allocator, timeouts));
+            unsafe {
+                squeue.push_multiple(&ops).expect("Failed to push ops");
+            }
+            return Ok(ops.len());
+        }
+
         let tx_buffer = allocator.alloc_buf(BufferDirection::TX, Some(req.as_bytes()));
         let op_send_idx = allocator
             .alloc_entry(EntryInfo {
@@ -235,7 +606,7 @@ impl Scan for ScanHttpHeaderMatch {
         let op_send = opcode::WriteFixed::new(
             Fd(sckt),
             tx_buffer.iov.iov_base.cast::<u8>(),
-            tx_buffer.iov.iov_len as u32,
+            req.len() as u32,
             tx_buffer.idx as u16,
         )
         .build()
@@ -322,22 +693,171 @@ impl Scan for ScanHttpHeaderMatch {
         Ok(ops.len())
     }
 
-    fn socket(&self) -> RawFd {
+    fn push_followup_ops(
+        &mut self,
+        squeue: &mut SubmissionQueue,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> usize {
+        let mut count = 0;
+        while let Some(&sckt) = self.pending_tls.last() {
+            if !allocator.has_free_entry_count(4) || squeue.capacity() - squeue.len() < 4 {
+                break;
+            }
+            self.pending_tls.pop();
+            let ops = self.tls_exchange_ops(sckt, allocator, timeouts);
+            unsafe {
+                squeue.push_multiple(&ops).expect("Failed to push ops");
+            }
+            count += ops.len();
+        }
+        count
+    }
+
+    fn socket(&self, family: AddressFamily) -> RawFd {
         socket(
-            AddressFamily::Inet,
+            family,
             SockType::Stream,
             SockFlag::empty(),
             None,
         )
         .expect("Failed to create TCP socket")
     }
+
+    fn finish(&mut self, out: &mut dyn io::Write) -> io::Result<()> {
+        if self.opts.capture_header.is_none() {
+            return Ok(());
+        }
+        // Group IPs by captured value, and print the most common values first
+        let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (addr, captured) in &self.captured {
+            groups.entry(captured).or_default().push(addr);
+        }
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(b.0)));
+        for (captured, mut addrs) in groups {
+            addrs.sort();
+            let addrs: Vec<_> = addrs.iter().map(|addr| addr.to_string()).collect();
+            writeln!(
+                out,
+                "{}\t{} IPs\t{}",
+                captured.as_deref().unwrap_or("-"),
+                addrs.len(),
+                addrs.join(",")
+            )?;
+        }
+        Ok(())
+    }
+
+    fn save_results(&self) -> serde_json::Value {
+        // JSON object keys must be strings, so save captured values as pairs
+        let captured: Vec<_> = self.captured.iter().collect();
+        serde_json::to_value(captured).unwrap()
+    }
+
+    fn load_results(&mut self, results: serde_json::Value) -> serde_json::Result<()> {
+        if !results.is_null() {
+            let captured: Vec<(SocketAddr, Option<String>)> = serde_json::from_value(results)?;
+            self.captured = captured.into_iter().collect();
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use base64::Engine;
     use bstr::B;
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::{ServerConfig, ServerConnection};
+
+    use crate::config::ResponseHttpHeaderRegex;
+    use crate::tls::{TEST_CERT, TEST_KEY};
+
+    fn scan(rules: &[&str], capture: Option<&str>) -> ScanHttpHeaderMatch {
+        ScanHttpHeaderMatch::new(&HttpHeaderMatchScanOptions {
+            request_verb: "GET".to_string(),
+            request_uri: "/".to_string(),
+            request_host: None,
+            tls: false,
+            sni: None,
+            request_headers: vec![],
+            response_header_regexs: rules.iter().map(|r| r.parse().unwrap()).collect(),
+            capture_header: capture.map(|c| c.parse::<ResponseHttpHeaderRegex>().unwrap()),
+        })
+    }
+
+    const RESPONSE: &[u8] = b"HTTP/1.1 403 Forbidden\r\nServer: cloudflare\r\ncf-ray: 7c1f3a5b9e2d0a1b-SJC\r\n\r\nbody: not a header\r\n";
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(scan(&[], None).parse_response(RESPONSE), (true, None));
+        assert_eq!(
+            scan(&["Server: ^cloudflare$"], None).parse_response(RESPONSE),
+            (true, None)
+        );
+        assert_eq!(
+            scan(&["Server: ^cloudflare$", "Server: ^nginx"], None).parse_response(RESPONSE),
+            (false, None)
+        );
+        assert_eq!(
+            scan(&["body: .*"], None).parse_response(RESPONSE),
+            (false, None)
+        );
+    }
+
+    #[test]
+    fn test_parse_response_capture() {
+        assert_eq!(
+            scan(&[], Some("CF-RAY: -([A-Z]+)$")).parse_response(RESPONSE),
+            (true, Some(B("SJC")))
+        );
+        assert_eq!(
+            scan(&[], Some("CF-RAY: [A-Z]+$")).parse_response(RESPONSE),
+            (true, Some(B("SJC")))
+        );
+        assert_eq!(
+            scan(&[], Some("CF-RAY: ^[0-9]+$")).parse_response(RESPONSE),
+            (true, None)
+        );
+        assert_eq!(
+            scan(&[], Some("X-Served-By: (.*)")).parse_response(RESPONSE),
+            (true, None)
+        );
+    }
+
+    #[test]
+    fn test_capture_summary() {
+        let mut http = scan(&[], Some("CF-RAY: -([A-Z]+)$"));
+        let mut respond = |addr: &str, response: &[u8]| {
+            let result = ScanResult::new(addr.parse().unwrap(), ScanType::HttpHeaderMatch);
+            http.results.insert(3, result);
+            http.handle_response(3, response);
+            http.results.remove(3).unwrap();
+        };
+        // Each round of a multi round scan, the colo may change between rounds
+        respond("10.0.0.1:80", RESPONSE);
+        respond("10.0.0.1:80", RESPONSE);
+        respond("10.0.0.1:80", &RESPONSE.replace("-SJC", "-LAX"));
+        respond("10.0.0.2:80", &RESPONSE.replace("-SJC", "-LAX"));
+        respond("10.0.0.3:80", b"HTTP/1.1 200 OK\r\n\r\n");
+
+        let summary = |scan: &mut ScanHttpHeaderMatch| {
+            let mut out = Vec::new();
+            scan.finish(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let expected = "-\t1 IPs\t10.0.0.3:80\n\
+                        LAX\t1 IPs\t10.0.0.2:80\n\
+                        SJC\t1 IPs\t10.0.0.1:80\n";
+        assert_eq!(summary(&mut http), expected);
+
+        let mut resumed = scan(&[], Some("CF-RAY: -([A-Z]+)$"));
+        resumed.load_results(http.save_results()).unwrap();
+        assert_eq!(summary(&mut resumed), expected);
+    }
 
     #[test]
     fn test_parse_header_line() {
@@ -363,4 +883,77 @@ mod tests {
             Some((B("Server"), B(": srv 1.2.3")))
         );
     }
+
+    fn tls_server() -> ServerConnection {
+        let decode = |b64| base64::engine::general_purpose::STANDARD.decode(b64).unwrap();
+        let config = ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(decode(TEST_CERT))],
+            PrivateKeyDer::Pkcs8(decode(TEST_KEY).into()),
+        )
+        .unwrap();
+        ServerConnection::new(Arc::new(config)).unwrap()
+    }
+
+    #[test]
+    fn test_tls_target() {
+        let request = b"GET / HTTP/1.1\r\nHost: www.example.com\r\n\r\n";
+        let mut client = TlsTarget::new(
+            Rc::new(SockaddrStorage::from(
+                "192.0.2.1:443".parse::<SocketAddr>().unwrap(),
+            )),
+            &ScanHttpHeaderMatch::tls_config(),
+            ServerName::try_from("example.com").unwrap(),
+            request,
+        );
+        let mut server = tls_server();
+        let mut received = Vec::new();
+        let mut responded = false;
+
+        // Shuttle records between both ends, as the scan does with io_uring ops
+        for _ in 0..8 {
+            if client.is_done() {
+                break;
+            }
+            let records = std::mem::take(&mut client.outgoing);
+            let mut records = &records[..];
+            while !records.is_empty() {
+                server.read_tls(&mut records).unwrap();
+                server.process_new_packets().unwrap();
+            }
+            let _ = server.reader().read_to_end(&mut received);
+            if !responded && received.ends_with(b"\r\n\r\n") {
+                server.writer().write_all(RESPONSE).unwrap();
+                responded = true;
+            }
+            let mut reply = Vec::new();
+            while server.wants_write() {
+                server.write_tls(&mut reply).unwrap();
+            }
+            client.process(&reply).unwrap();
+        }
+
+        assert!(client.is_done());
+        assert_eq!(server.server_name(), Some("example.com"));
+        assert_eq!(received, request);
+        assert_eq!(client.response, RESPONSE);
+        assert_eq!(
+            scan(&["Server: ^cloudflare$"], None).parse_response(&client.response),
+            (true, None)
+        );
+
+        // A plain HTTP server answering the ClientHello
+        let mut client = TlsTarget::new(
+            Rc::clone(&client.addr),
+            &ScanHttpHeaderMatch::tls_config(),
+            ServerName::try_from("example.com").unwrap(),
+            request,
+        );
+        assert!(client.process(b"HTTP/1.1 400 Bad Request\r\n\r\n").is_err());
+    }
 }
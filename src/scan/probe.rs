@@ -0,0 +1,307 @@
+//! Probe scan to send a user supplied payload and match the response
+
+use std::rc::Rc;
+
+use io_uring::{cqueue, opcode, squeue, types::Fd, Probe};
+use nix::{
+    errno::Errno,
+    libc,
+    sys::socket::{socket, AddressFamily, SockFlag, SockType, SockaddrLike},
+    unistd,
+};
+
+use crate::config::ProbeScanOptions;
+use crate::result::{ScanResult, ScanType, State};
+use crate::ring::{BufferDirection, BufferInfo, EntryInfo, RingAllocator};
+use crate::scan::{
+    check_op_supported, socket_addr, PendingResults, PushError, RawFd, Scan, SockaddrStorage,
+    Timeouts,
+};
+
+pub struct ScanProbe {
+    /// Payload sent to every target
+    payload: Vec<u8>,
+    /// Regex the response must match, all responses match if None
+    regex: Option<regex::bytes::Regex>,
+    /// Results of in progress targets
+    results: PendingResults,
+}
+
+/// Describes what scan step does an entry do
+#[derive(Debug)]
+enum EntryStep {
+    Connect = 0,
+    ConnectTimeout,
+    Send,
+    SendTimeout,
+    Recv,
+    RecvTimeout,
+    Close,
+}
+
+impl From<u8> for EntryStep {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Self::Connect,
+            1 => Self::ConnectTimeout,
+            2 => Self::Send,
+            3 => Self::SendTimeout,
+            4 => Self::Recv,
+            5 => Self::RecvTimeout,
+            6 => Self::Close,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ScanProbe {
+    /// Record server response and whether it matches
+    fn handle_response(&mut self, fd: RawFd, buf: &[u8]) {
+        let matched = self.regex.as_ref().map_or(true, |r| r.is_match(buf));
+        if let Some(result) = self.results.get_mut(fd) {
+            result.banner = Some(buf.to_vec());
+            result.matched = matched;
+            if let (true, Some(regex)) = (matched, self.regex.as_ref()) {
+                result.rules = vec![regex.to_string()];
+            }
+        }
+    }
+
+    pub fn new(opts: &ProbeScanOptions) -> Self {
+        let payload = opts
+            .payload
+            .as_ref()
+            .or(opts.payload_file.as_ref())
+            .expect("Payload is required");
+        Self {
+            payload: payload.0.clone(),
+            regex: opts.regex.clone(),
+            results: PendingResults::default(),
+        }
+    }
+}
+
+impl Scan for ScanProbe {
+    fn check_supported(&self, probe: &Probe) -> bool {
+        check_op_supported(probe, opcode::Connect::CODE, "connect") &&
+        check_op_supported(probe, opcode::LinkTimeout::CODE, "link timeout") &&
+        check_op_supported(probe, opcode::WriteFixed::CODE, "write fixed") &&
+        check_op_supported(probe, opcode::ReadFixed::CODE, "read fixed") &&
+        check_op_supported(probe, opcode::Close::CODE, "close")
+    }
+
+    fn max_tx_size(&mut self) -> Option<usize> {
+        Some(self.payload.len())
+    }
+
+    fn ops_per_ip(&self) -> usize {
+        7
+    }
+
+    fn step_name(&self, step: u8) -> String {
+        format!("{:?}", EntryStep::from(step))
+    }
+
+    fn process_completed_entry(
+        &mut self,
+        cq_entry: &cqueue::Entry,
+        entry_info: &EntryInfo,
+        ring_allocator: &RingAllocator,
+    ) -> Option<ScanResult> {
+        let step = EntryStep::from(entry_info.step);
+        let errno = Errno::from_i32(-cq_entry.result());
+        log::debug!(
+            "op #{} ({:?} {}) returned {} ({:?})",
+            cq_entry.user_data(),
+            step,
+            entry_info.ip,
+            cq_entry.result(),
+            errno
+        );
+        if let Some(buf) = entry_info.buf.as_ref() {
+            log::debug!(
+                "buf: {:?}",
+                String::from_utf8_lossy(ring_allocator.get_buf(buf.idx))
+            );
+        }
+        match step {
+            EntryStep::Connect => {
+                if let Some(result) = self.results.get_mut(entry_info.fd) {
+                    result.state = State::from_connect(cq_entry.result());
+                    if result.state == State::Open {
+                        result.latency = Some(entry_info.start.elapsed());
+                    }
+                }
+                None
+            }
+            EntryStep::Recv => {
+                let ret = cq_entry.result();
+                if ret > 0 {
+                    let buf = ring_allocator.get_buf(entry_info.buf.as_ref().unwrap().idx);
+                    self.handle_response(entry_info.fd, &buf[..ret as usize]);
+                }
+                None
+            }
+            EntryStep::Close => {
+                if cq_entry.result() == -libc::ECANCELED {
+                    // if a previous entry errored and the socket close was canceled, do it now to avoid fd leak
+                    unistd::close(entry_info.fd).unwrap();
+                }
+                self.results.remove(entry_info.fd)
+            }
+            _ => None,
+        }
+    }
+
+    fn push_scan_ops(
+        &mut self,
+        sckt: RawFd,
+        addr: &SockaddrStorage,
+        squeue: &mut io_uring::squeue::SubmissionQueue,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> Result<usize, PushError> {
+        let addr = Rc::new(addr.to_owned());
+        self.results
+            .insert(sckt, ScanResult::new(socket_addr(&addr), ScanType::Probe));
+
+        let entry_connect_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Connect as u8,
+                buf: None,
+                fd: sckt,
+                start: std::time::Instant::now(),
+            })
+            .unwrap();
+        let op_connect = opcode::Connect::new(Fd(sckt), addr.as_ptr(), addr.len())
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_connect_idx);
+
+        let entry_connect_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::ConnectTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: std::time::Instant::now(),
+            })
+            .unwrap();
+        let op_connect_timeout = opcode::LinkTimeout::new(&timeouts.connect)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_connect_timeout_idx);
+
+        let tx_buffer = allocator.alloc_buf(BufferDirection::TX, Some(&self.payload));
+        let op_send_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Send as u8,
+                buf: Some(BufferInfo {
+                    idx: tx_buffer.idx,
+                    direction: BufferDirection::TX,
+                }),
+                fd: sckt,
+                start: std::time::Instant::now(),
+            })
+            .unwrap();
+        let op_send = opcode::WriteFixed::new(
+            Fd(sckt),
+            tx_buffer.iov.iov_base.cast::<u8>(),
+            tx_buffer.iov.iov_len as u32,
+            tx_buffer.idx as u16,
+        )
+        .build()
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(op_send_idx);
+
+        let entry_send_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::SendTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: std::time::Instant::now(),
+            })
+            .unwrap();
+        let op_send_timeout = opcode::LinkTimeout::new(&timeouts.write)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_send_timeout_idx);
+
+        let rx_buffer = allocator.alloc_buf(BufferDirection::RX, None);
+        let op_recv_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Recv as u8,
+                buf: Some(BufferInfo {
+                    idx: rx_buffer.idx,
+                    direction: BufferDirection::RX,
+                }),
+                fd: sckt,
+                start: std::time::Instant::now(),
+            })
+            .unwrap();
+        let op_recv = opcode::ReadFixed::new(
+            Fd(sckt),
+            rx_buffer.iov.iov_base.cast::<u8>(),
+            rx_buffer.iov.iov_len as u32,
+            rx_buffer.idx as u16,
+        )
+        .build()
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(op_recv_idx);
+
+        let entry_recv_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::RecvTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: std::time::Instant::now(),
+            })
+            .unwrap();
+        let op_recv_timeout = opcode::LinkTimeout::new(&timeouts.read)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_recv_timeout_idx);
+
+        let entry_close_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Close as u8,
+                buf: None,
+                fd: sckt,
+                start: std::time::Instant::now(),
+            })
+            .unwrap();
+        let op_close = opcode::Close::new(Fd(sckt))
+            .build()
+            .user_data(entry_close_idx);
+
+        let ops = [
+            op_connect,
+            op_connect_timeout,
+            op_send,
+            op_send_timeout,
+            op_recv,
+            op_recv_timeout,
+            op_close,
+        ];
+        unsafe {
+            squeue.push_multiple(&ops).expect("Failed to push ops");
+        }
+        Ok(ops.len())
+    }
+
+    fn socket(&self, family: AddressFamily) -> RawFd {
+        socket(
+            family,
+            SockType::Stream,
+            SockFlag::empty(),
+            None,
+        )
+        .expect("Failed to create TCP socket")
+    }
+}
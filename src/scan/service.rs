@@ -0,0 +1,382 @@
+//! Service scan to detect services and versions with nmap-service-probes fingerprints
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::rc::Rc;
+use std::time::Instant;
+
+use io_uring::{cqueue, opcode, squeue, types::Fd, Probe};
+use nix::{
+    errno::Errno,
+    libc,
+    sys::socket::{socket, AddressFamily, SockFlag, SockType, SockaddrLike},
+    unistd,
+};
+
+use crate::config::ServiceScanOptions;
+use crate::result::{ScanResult, ScanType, State};
+use crate::ring::{BufferDirection, BufferInfo, EntryInfo, RingAllocator};
+use crate::scan::{
+    check_op_supported, socket_addr, PushError, RawFd, Scan, SockaddrStorage, SubmissionQueue,
+    Timeouts,
+};
+use crate::service_probes::ServiceProbes;
+
+pub struct ScanService {
+    opts: ServiceScanOptions,
+    probes: ServiceProbes,
+    /// In progress targets, by socket
+    targets: HashMap<RawFd, Target>,
+    /// Sockets that need their next probe to be pushed
+    pending_probes: Vec<RawFd>,
+}
+
+/// State of a single target
+///
+/// All probes are sent on the connection opened for the NULL probe, one after the other,
+/// as long as the server neither matches nor closes the connection
+struct Target {
+    addr: Rc<SockaddrStorage>,
+    result: ScanResult,
+    /// Probe whose response is being read, None while waiting for a banner without a NULL probe
+    current: Option<usize>,
+    /// Probes left to send
+    probes: VecDeque<usize>,
+    /// Set when sending a probe failed, the connection can not be used anymore
+    broken: bool,
+}
+
+/// Describes what scan step does an entry do
+#[derive(Debug)]
+enum EntryStep {
+    Connect = 0,
+    ConnectTimeout,
+    Send,
+    SendTimeout,
+    Recv,
+    RecvTimeout,
+}
+
+impl From<u8> for EntryStep {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Self::Connect,
+            1 => Self::ConnectTimeout,
+            2 => Self::Send,
+            3 => Self::SendTimeout,
+            4 => Self::Recv,
+            5 => Self::RecvTimeout,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ScanService {
+    pub fn new(opts: &ServiceScanOptions) -> io::Result<Self> {
+        Ok(Self {
+            opts: opts.to_owned(),
+            probes: ServiceProbes::load(&opts.probes_file)?,
+            targets: HashMap::new(),
+            pending_probes: Vec::new(),
+        })
+    }
+
+    /// Match a probe response, return true if the service is identified and no more probes are needed
+    fn handle_response(&mut self, fd: RawFd, buf: &[u8]) -> bool {
+        let Some(target) = self.targets.get_mut(&fd) else {
+            return false;
+        };
+        target.result.banner = Some(buf.to_vec());
+        let Some(service) = target
+            .current
+            .and_then(|probe| self.probes.match_response(probe, buf))
+        else {
+            return false;
+        };
+        let soft = service.soft;
+        // A softmatch only names the service, keep probing for a full match
+        if !soft || target.result.service.is_none() {
+            target.result.service = Some(service);
+        }
+        !soft
+    }
+
+    /// Close socket and report the detected service
+    fn finish_target(&mut self, fd: RawFd) -> Option<ScanResult> {
+        let mut target = self.targets.remove(&fd)?;
+        unistd::close(fd).unwrap();
+        target.result.matched = target.result.service.is_some();
+        Some(target.result)
+    }
+
+    /// Build a read op, and its timeout
+    fn recv_ops(
+        sckt: RawFd,
+        addr: &Rc<SockaddrStorage>,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> [squeue::Entry; 2] {
+        let rx_buffer = allocator.alloc_buf(BufferDirection::RX, None);
+        let op_recv_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(addr),
+                step: EntryStep::Recv as u8,
+                buf: Some(BufferInfo {
+                    idx: rx_buffer.idx,
+                    direction: BufferDirection::RX,
+                }),
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_recv = opcode::ReadFixed::new(
+            Fd(sckt),
+            rx_buffer.iov.iov_base.cast::<u8>(),
+            rx_buffer.iov.iov_len as u32,
+            rx_buffer.idx as u16,
+        )
+        .build()
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(op_recv_idx);
+
+        let entry_recv_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(addr),
+                step: EntryStep::RecvTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_recv_timeout = opcode::LinkTimeout::new(&timeouts.read)
+            .build()
+            .user_data(entry_recv_timeout_idx);
+
+        [op_recv, op_recv_timeout]
+    }
+
+    /// Build the ops sending a probe, and reading its response
+    fn probe_ops(
+        &self,
+        sckt: RawFd,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> [squeue::Entry; 4] {
+        let target = &self.targets[&sckt];
+        let payload = &self.probes.get(target.current.unwrap()).payload;
+
+        let tx_buffer = allocator.alloc_buf(BufferDirection::TX, Some(payload));
+        let op_send_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&target.addr),
+                step: EntryStep::Send as u8,
+                buf: Some(BufferInfo {
+                    idx: tx_buffer.idx,
+                    direction: BufferDirection::TX,
+                }),
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        // The buffer is sized for the longest probe, only send this one
+        let op_send = opcode::WriteFixed::new(
+            Fd(sckt),
+            tx_buffer.iov.iov_base.cast::<u8>(),
+            payload.len() as u32,
+            tx_buffer.idx as u16,
+        )
+        .build()
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(op_send_idx);
+
+        let entry_send_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&target.addr),
+                step: EntryStep::SendTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_send_timeout = opcode::LinkTimeout::new(&timeouts.write)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_send_timeout_idx);
+
+        let [op_recv, op_recv_timeout] = Self::recv_ops(sckt, &target.addr, allocator, timeouts);
+        [op_send, op_send_timeout, op_recv, op_recv_timeout]
+    }
+}
+
+impl Scan for ScanService {
+    fn check_supported(&self, probe: &Probe) -> bool {
+        check_op_supported(probe, opcode::Connect::CODE, "connect") &&
+        check_op_supported(probe, opcode::LinkTimeout::CODE, "link timeout") &&
+        check_op_supported(probe, opcode::WriteFixed::CODE, "write fixed") &&
+        check_op_supported(probe, opcode::ReadFixed::CODE, "read fixed")
+    }
+
+    fn max_tx_size(&mut self) -> Option<usize> {
+        Some(self.probes.max_payload_len()).filter(|&len| len > 0)
+    }
+
+    fn ops_per_ip(&self) -> usize {
+        4
+    }
+
+    fn step_name(&self, step: u8) -> String {
+        format!("{:?}", EntryStep::from(step))
+    }
+
+    fn process_completed_entry(
+        &mut self,
+        cq_entry: &cqueue::Entry,
+        entry_info: &EntryInfo,
+        ring_allocator: &RingAllocator,
+    ) -> Option<ScanResult> {
+        let step = EntryStep::from(entry_info.step);
+        let errno = Errno::from_i32(-cq_entry.result());
+        log::debug!(
+            "op #{} ({:?} {}) returned {} ({:?})",
+            cq_entry.user_data(),
+            step,
+            entry_info.ip,
+            cq_entry.result(),
+            errno
+        );
+        match step {
+            EntryStep::Connect => {
+                if let Some(target) = self.targets.get_mut(&entry_info.fd) {
+                    target.result.state = State::from_connect(cq_entry.result());
+                    if target.result.state == State::Open {
+                        target.result.latency = Some(entry_info.start.elapsed());
+                    }
+                }
+                None
+            }
+            EntryStep::Send => {
+                if cq_entry.result() < 0 {
+                    if let Some(target) = self.targets.get_mut(&entry_info.fd) {
+                        target.broken = true;
+                    }
+                }
+                None
+            }
+            EntryStep::Recv => {
+                let ret = cq_entry.result();
+                if ret > 0 {
+                    let buf = ring_allocator.get_buf(entry_info.buf.as_ref().unwrap().idx);
+                    if self.handle_response(entry_info.fd, &buf[..ret as usize]) {
+                        return self.finish_target(entry_info.fd);
+                    }
+                }
+                // A recv canceled by its timeout got no response, the next probe can still be sent,
+                // but not after a failed connect or send, a closed connection or another error
+                let target = self.targets.get_mut(&entry_info.fd)?;
+                let usable = target.result.state == State::Open
+                    && !target.broken
+                    && (ret > 0 || ret == -libc::ECANCELED);
+                match target.probes.pop_front() {
+                    Some(next) if usable => {
+                        target.current = Some(next);
+                        self.pending_probes.push(entry_info.fd);
+                        None
+                    }
+                    _ => self.finish_target(entry_info.fd),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn push_scan_ops(
+        &mut self,
+        sckt: RawFd,
+        addr: &SockaddrStorage,
+        squeue: &mut io_uring::squeue::SubmissionQueue,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> Result<usize, PushError> {
+        let addr = Rc::new(addr.to_owned());
+        let result = ScanResult::new(socket_addr(&addr), ScanType::Service);
+        let probes = self
+            .probes
+            .probes_for_port(result.addr.port(), self.opts.intensity)
+            .into_iter()
+            .take(self.opts.max_probes)
+            .collect();
+        self.targets.insert(
+            sckt,
+            Target {
+                addr: Rc::clone(&addr),
+                result,
+                current: self.probes.null(),
+                probes,
+                broken: false,
+            },
+        );
+
+        let entry_connect_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Connect as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_connect = opcode::Connect::new(Fd(sckt), addr.as_ptr(), addr.len())
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_connect_idx);
+
+        let entry_connect_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::ConnectTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_connect_timeout = opcode::LinkTimeout::new(&timeouts.connect)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_connect_timeout_idx);
+
+        // The NULL probe only waits for a banner
+        let [op_recv, op_recv_timeout] = Self::recv_ops(sckt, &addr, allocator, timeouts);
+
+        let ops = [op_connect, op_connect_timeout, op_recv, op_recv_timeout];
+        unsafe {
+            squeue.push_multiple(&ops).expect("Failed to push ops");
+        }
+        Ok(ops.len())
+    }
+
+    fn push_followup_ops(
+        &mut self,
+        squeue: &mut SubmissionQueue,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> usize {
+        let mut count = 0;
+        while let Some(&sckt) = self.pending_probes.last() {
+            if !allocator.has_free_entry_count(4) || squeue.capacity() - squeue.len() < 4 {
+                break;
+            }
+            self.pending_probes.pop();
+            let ops = self.probe_ops(sckt, allocator, timeouts);
+            unsafe {
+                squeue.push_multiple(&ops).expect("Failed to push ops");
+            }
+            count += ops.len();
+        }
+        count
+    }
+
+    fn socket(&self, family: AddressFamily) -> RawFd {
+        socket(family, SockType::Stream, SockFlag::empty(), None)
+            .expect("Failed to create TCP socket")
+    }
+}
@@ -0,0 +1,373 @@
+//! HTTP download throughput test
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use io_uring::{cqueue, opcode, squeue, types::Fd, Probe};
+use nix::{
+    errno::Errno,
+    sys::socket::{socket, AddressFamily, SockFlag, SockType, SockaddrLike},
+    unistd,
+};
+
+use crate::config::SpeedTestScanOptions;
+use crate::result::{ScanResult, ScanType, State};
+use crate::ring::{BufferDirection, BufferInfo, EntryInfo, RingAllocator};
+use crate::scan::{
+    check_op_supported, longest_addr, socket_addr, PushError, RawFd, Scan, SockaddrStorage,
+    SubmissionQueue, Timeouts,
+};
+
+pub struct ScanSpeedTest {
+    opts: SpeedTestScanOptions,
+    tx_buf_size: Option<usize>,
+    /// In progress transfers, by socket
+    transfers: HashMap<RawFd, Transfer>,
+    /// Sockets that need another read to be pushed
+    pending_reads: Vec<RawFd>,
+}
+
+/// State of a single download
+struct Transfer {
+    addr: Rc<SockaddrStorage>,
+    /// Port state from the connect result
+    state: State,
+    /// Time to connect, set when the connection succeeded
+    latency: Option<Duration>,
+    /// Set when the request has been sent, the throughput is measured from there
+    start: Option<Instant>,
+    /// Time of the last successful read
+    end: Option<Instant>,
+    bytes: u64,
+}
+
+/// Describes what scan step does an entry do
+#[derive(Debug)]
+enum EntryStep {
+    Connect = 0,
+    ConnectTimeout,
+    Send,
+    SendTimeout,
+    Recv,
+    RecvTimeout,
+}
+
+impl From<u8> for EntryStep {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Self::Connect,
+            1 => Self::ConnectTimeout,
+            2 => Self::Send,
+            3 => Self::SendTimeout,
+            4 => Self::Recv,
+            5 => Self::RecvTimeout,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ScanSpeedTest {
+    pub fn new(opts: &SpeedTestScanOptions) -> Self {
+        Self {
+            opts: opts.to_owned(),
+            tx_buf_size: None,
+            transfers: HashMap::new(),
+            pending_reads: Vec::new(),
+        }
+    }
+
+    fn format_request(&self, addr: &SockaddrStorage) -> String {
+        let mut s = if let Some(size_hint) = self.tx_buf_size {
+            String::with_capacity(size_hint)
+        } else {
+            String::new()
+        };
+        write!(&mut s, "GET {} HTTP/1.1\r\nHost: ", self.opts.request_uri).unwrap();
+        match &self.opts.request_host {
+            Some(host) => write!(&mut s, "{}", host).unwrap(),
+            None => write!(&mut s, "{}", addr).unwrap(),
+        }
+        write!(&mut s, "\r\nConnection: close\r\n").unwrap();
+        for hdr in &self.opts.request_headers {
+            write!(&mut s, "{}: {}\r\n", hdr.key, hdr.val).unwrap();
+        }
+        write!(&mut s, "\r\n").unwrap();
+        s
+    }
+
+    /// Close socket and report throughput
+    fn finish_transfer(&mut self, fd: RawFd) -> ScanResult {
+        let transfer = self.transfers.remove(&fd).unwrap();
+        unistd::close(fd).unwrap();
+
+        let mut result = ScanResult::new(socket_addr(&transfer.addr), ScanType::SpeedTest);
+        result.state = transfer.state;
+        result.latency = transfer.latency;
+        if let (Some(start), Some(end)) = (transfer.start, transfer.end) {
+            result.transfer = Some(crate::result::Transfer {
+                bytes: transfer.bytes,
+                duration: end.duration_since(start),
+            });
+            result.matched = true;
+        }
+        result
+    }
+
+    /// Build a read op, and its timeout
+    fn recv_ops(
+        sckt: RawFd,
+        addr: &Rc<SockaddrStorage>,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> [squeue::Entry; 2] {
+        let rx_buffer = allocator.alloc_buf(BufferDirection::RX, None);
+        let op_recv_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(addr),
+                step: EntryStep::Recv as u8,
+                buf: Some(BufferInfo {
+                    idx: rx_buffer.idx,
+                    direction: BufferDirection::RX,
+                }),
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_recv = opcode::ReadFixed::new(
+            Fd(sckt),
+            rx_buffer.iov.iov_base.cast::<u8>(),
+            rx_buffer.iov.iov_len as u32,
+            rx_buffer.idx as u16,
+        )
+        .build()
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(op_recv_idx);
+
+        let entry_recv_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(addr),
+                step: EntryStep::RecvTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_recv_timeout = opcode::LinkTimeout::new(&timeouts.read)
+            .build()
+            .user_data(entry_recv_timeout_idx);
+
+        [op_recv, op_recv_timeout]
+    }
+}
+
+impl Scan for ScanSpeedTest {
+    fn check_supported(&self, probe: &Probe) -> bool {
+        check_op_supported(probe, opcode::Connect::CODE, "connect")
+            && check_op_supported(probe, opcode::LinkTimeout::CODE, "link timeout")
+            && check_op_supported(probe, opcode::WriteFixed::CODE, "write fixed")
+            && check_op_supported(probe, opcode::ReadFixed::CODE, "read fixed")
+    }
+
+    fn max_tx_size(&mut self) -> Option<usize> {
+        let sz = self.format_request(&longest_addr()).len();
+        self.tx_buf_size = Some(sz);
+        Some(sz)
+    }
+
+    fn ops_per_ip(&self) -> usize {
+        6
+    }
+
+    fn step_name(&self, step: u8) -> String {
+        format!("{:?}", EntryStep::from(step))
+    }
+
+    fn process_completed_entry(
+        &mut self,
+        cq_entry: &cqueue::Entry,
+        entry_info: &EntryInfo,
+        _ring_allocator: &RingAllocator,
+    ) -> Option<ScanResult> {
+        let step = EntryStep::from(entry_info.step);
+        let errno = Errno::from_i32(-cq_entry.result());
+        log::debug!(
+            "op #{} ({:?} {}) returned {} ({:?})",
+            cq_entry.user_data(),
+            step,
+            entry_info.ip,
+            cq_entry.result(),
+            errno
+        );
+        match step {
+            EntryStep::Connect => {
+                let transfer = self.transfers.get_mut(&entry_info.fd).unwrap();
+                transfer.state = State::from_connect(cq_entry.result());
+                if transfer.state == State::Open {
+                    transfer.latency = Some(entry_info.start.elapsed());
+                }
+                None
+            }
+            EntryStep::Send => {
+                if cq_entry.result() > 0 {
+                    self.transfers.get_mut(&entry_info.fd).unwrap().start = Some(Instant::now());
+                }
+                None
+            }
+            EntryStep::Recv => {
+                let ret = cq_entry.result();
+                if ret <= 0 {
+                    // EOF, error, or canceled because a previous step failed
+                    return Some(self.finish_transfer(entry_info.fd));
+                }
+
+                let transfer = self.transfers.get_mut(&entry_info.fd).unwrap();
+                let now = Instant::now();
+                transfer.bytes += ret as u64;
+                transfer.end = Some(now);
+                let elapsed = now.duration_since(transfer.start.unwrap_or(now));
+                if transfer.bytes >= self.opts.max_bytes
+                    || elapsed >= Duration::from_secs(self.opts.max_time_secs)
+                {
+                    Some(self.finish_transfer(entry_info.fd))
+                } else {
+                    self.pending_reads.push(entry_info.fd);
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn push_scan_ops(
+        &mut self,
+        sckt: RawFd,
+        addr: &SockaddrStorage,
+        squeue: &mut SubmissionQueue,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> Result<usize, PushError> {
+        let addr = Rc::new(addr.to_owned());
+
+        let entry_connect_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Connect as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_connect = opcode::Connect::new(Fd(sckt), addr.as_ptr(), addr.len())
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_connect_idx);
+
+        let entry_connect_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::ConnectTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_connect_timeout = opcode::LinkTimeout::new(&timeouts.connect)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_connect_timeout_idx);
+
+        let req = self.format_request(&addr);
+        let tx_buffer = allocator.alloc_buf(BufferDirection::TX, Some(req.as_bytes()));
+        let op_send_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Send as u8,
+                buf: Some(BufferInfo {
+                    idx: tx_buffer.idx,
+                    direction: BufferDirection::TX,
+                }),
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_send = opcode::WriteFixed::new(
+            Fd(sckt),
+            tx_buffer.iov.iov_base.cast::<u8>(),
+            req.len() as u32,
+            tx_buffer.idx as u16,
+        )
+        .build()
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(op_send_idx);
+
+        let entry_send_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::SendTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_send_timeout = opcode::LinkTimeout::new(&timeouts.write)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_send_timeout_idx);
+
+        let [op_recv, op_recv_timeout] = Self::recv_ops(sckt, &addr, allocator, timeouts);
+
+        self.transfers.insert(
+            sckt,
+            Transfer {
+                addr,
+                state: State::Filtered,
+                latency: None,
+                start: None,
+                end: None,
+                bytes: 0,
+            },
+        );
+
+        let ops = [
+            op_connect,
+            op_connect_timeout,
+            op_send,
+            op_send_timeout,
+            op_recv,
+            op_recv_timeout,
+        ];
+        unsafe {
+            squeue.push_multiple(&ops).expect("Failed to push ops");
+        }
+        Ok(ops.len())
+    }
+
+    fn push_followup_ops(
+        &mut self,
+        squeue: &mut SubmissionQueue,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> usize {
+        let mut count = 0;
+        while let Some(&sckt) = self.pending_reads.last() {
+            if !allocator.has_free_entry_count(2) || squeue.capacity() - squeue.len() < 2 {
+                break;
+            }
+            self.pending_reads.pop();
+            let addr = Rc::clone(&self.transfers[&sckt].addr);
+            let ops = Self::recv_ops(sckt, &addr, allocator, timeouts);
+            unsafe {
+                squeue.push_multiple(&ops).expect("Failed to push ops");
+            }
+            count += ops.len();
+        }
+        count
+    }
+
+    fn socket(&self, family: AddressFamily) -> RawFd {
+        socket(family, SockType::Stream, SockFlag::empty(), None)
+            .expect("Failed to create TCP socket")
+    }
+}
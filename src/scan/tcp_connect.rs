@@ -1,4 +1,10 @@
-use std::{collections::HashSet, net::SocketAddr, rc::Rc};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    net::SocketAddr,
+    rc::Rc,
+};
 
 use io_uring::{cqueue, opcode, squeue, types::Fd, Probe};
 use nix::{
@@ -8,11 +14,22 @@ use nix::{
     unistd,
 };
 
+use crate::config::TcpConnectScanOptions;
+use crate::rank::rank;
+use crate::result::{ScanResult, ScanType, State};
 use crate::ring::{EntryInfo, RingAllocator};
-use crate::scan::{check_op_supported, PushError, RawFd, Scan, SockaddrIn, Timeouts};
+use crate::scan::{
+    check_op_supported, socket_addr, PendingResults, PushError, RawFd, Scan, SockaddrStorage,
+    Timeouts,
+};
+use crate::stats::{format_ms, LatencyStats};
 
 pub struct ScanTcpConnect {
-    set: HashSet<Rc<SockaddrIn>>,
+    opts: TcpConnectScanOptions,
+    // 每个目标在所有轮次中累积的连接延迟
+    stats: HashMap<SocketAddr, LatencyStats>,
+    // 正在扫描的目标
+    results: PendingResults,
 }
 
 // 枚举类型，表示 IO 请求的不同阶段
@@ -37,9 +54,11 @@ impl From<u8> for EntryStep {
 }
 
 impl ScanTcpConnect {
-    pub fn new() -> Self {
+    pub fn new(opts: &TcpConnectScanOptions) -> Self {
         Self {
-            set: HashSet::new(),
+            opts: opts.to_owned(),
+            stats: HashMap::new(),
+            results: PendingResults::default(),
         }
     }
 }
@@ -63,13 +82,17 @@ impl Scan for ScanTcpConnect {
         3
     }
 
+    fn step_name(&self, step: u8) -> String {
+        format!("{:?}", EntryStep::from(step))
+    }
+
     // 处理已完成的 IO 请求
     fn process_completed_entry(
         &mut self,
         cq_entry: &cqueue::Entry,
         entry_info: &EntryInfo,
         ring_allocator: &RingAllocator,
-    ) -> bool {
+    ) -> Option<ScanResult> {
         // 获取当前IO请求状态
         let step = EntryStep::from(entry_info.step);
         // 将 IO 请求的返回值转换成 Errno 枚举类型，并记录到日志中
@@ -93,17 +116,27 @@ impl Scan for ScanTcpConnect {
         match step {
             // Connect 请求完成
             EntryStep::Connect => {
-                // 如果返回值为 0，表示连接成功
-                let ret = cq_entry.result();
-                if ret == 0 && !self.set.contains(&entry_info.ip) {
-                    // 打印成功连接的 IP 地址
-                    log::info!("{} \t delay: {}ms", &entry_info.ip, &entry_info.start.elapsed().as_millis());
-                    self.set.insert(entry_info.ip.clone());
+                // 每轮都会收到一次 Connect 的结果，返回值为 0 表示连接成功，其余都算作丢包
+                let stats = self.stats.entry(socket_addr(&entry_info.ip)).or_default();
+                let state = State::from_connect(cq_entry.result());
+                let latency = entry_info.start.elapsed();
+                if state == State::Open {
+                    log::debug!("{} \t delay: {}ms", entry_info.ip, latency.as_millis());
+                    stats.record_success(latency);
+                } else {
+                    stats.record_failure();
                 }
-                false
+                if let Some(result) = self.results.get_mut(entry_info.fd) {
+                    result.state = state;
+                    if state == State::Open {
+                        result.latency = Some(latency);
+                        result.matched = true;
+                    }
+                }
+                None
             }
             // 如果是 ConnectTimeout 状态，表示链接超时了
-            EntryStep::ConnectTimeout => false,
+            EntryStep::ConnectTimeout => None,
 
             // 如果是Close ，说明断开链接了
             EntryStep::Close => {
@@ -111,9 +144,9 @@ impl Scan for ScanTcpConnect {
                 if cq_entry.result() == -libc::ECANCELED {
                     unistd::close(entry_info.fd).unwrap();
                 }
-                true
+                self.results.remove(entry_info.fd)
             }
-            _ => false,
+            _ => None,
         }
     }
 
@@ -121,14 +154,16 @@ impl Scan for ScanTcpConnect {
     fn push_scan_ops(
         &mut self,
         sckt: RawFd, // 第一个参数，表示需要执行操作的 socket。RawFd 是 libc 库中定义的整型类型，用于表示文件描述符。
-        addr: &SockaddrIn, // 第二个参数，表示需要连接的远程地址。
+        addr: &SockaddrStorage, // 第二个参数，表示需要连接的远程地址。
         squeue: &mut io_uring::squeue::SubmissionQueue, // 表示操作提交队列，用于向内核提交 IO 操作。
         allocator: &mut RingAllocator,                  // 表示分配的环形缓冲区中的 Entry 分配器。
         timeouts: &Timeouts,                            // 表示连接超时时间和读写超时时间。
     ) -> Result<usize, PushError> {
-        // 如果一个函数尝试在接收到引用后持有 SockaddrIn 实例的所有权，而另一个函数在该函数持有实例的所有权之后仍然尝试访问该实例，就会出现未定义行为
-        // 为了避免可能的生命周期问题，使用 Rc 引用计数智能指针可以方便而且安全地管理 SockaddrIn 实例的生命周期
+        // 如果一个函数尝试在接收到引用后持有 SockaddrStorage 实例的所有权，而另一个函数在该函数持有实例的所有权之后仍然尝试访问该实例，就会出现未定义行为
+        // 为了避免可能的生命周期问题，使用 Rc 引用计数智能指针可以方便而且安全地管理 SockaddrStorage 实例的生命周期
         let addr = Rc::new(addr.to_owned()); // 将远程地址拷贝一份，并使用 Rc 包装。
+        self.results
+            .insert(sckt, ScanResult::new(socket_addr(&addr), ScanType::TcpConnect));
 
         // 分配一个新的 Entry，表示 Connect 请求
         let entry_connect_idx = allocator
@@ -192,15 +227,65 @@ impl Scan for ScanTcpConnect {
     }
 
     // 创建一个 TCP 套接字
-    fn socket(&self) -> RawFd {
+    fn socket(&self, family: AddressFamily) -> RawFd {
         socket(
-            AddressFamily::Inet,
+            family,
             SockType::Stream,
             SockFlag::empty(),
             None,
         )
         .expect("Failed to create TCP socket")
     }
+
+    // 对全部轮次的延迟统计排名，输出最好的 N 个 IP
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        // 先按地址排序，保证延迟和丢包率相同的 IP 输出顺序稳定
+        let mut all: Vec<_> = self.stats.iter().collect();
+        all.sort_by_key(|(addr, _)| **addr);
+        let ranked = rank(all, &self.opts.rank);
+        log::info!(
+            "{} IPs scanned, {} IPs ranked",
+            self.stats.len(),
+            ranked.len()
+        );
+
+        let mut file;
+        let out: &mut dyn Write = match &self.opts.rank.rank_output {
+            Some(path) => {
+                file = BufWriter::new(File::create(path)?);
+                &mut file
+            }
+            None => out,
+        };
+        for (addr, stats) in ranked {
+            writeln!(
+                out,
+                "{}\tmin: {}\tavg: {}\tmax: {}\tmedian: {}\tjitter: {}\tloss: {:.2}% ({}/{})",
+                addr,
+                format_ms(stats.min()),
+                format_ms(stats.avg()),
+                format_ms(stats.max()),
+                format_ms(stats.median()),
+                format_ms(stats.jitter()),
+                stats.loss_percent(),
+                stats.sent() - stats.received(),
+                stats.sent(),
+            )?;
+        }
+        out.flush()
+    }
+
+    // 检查点中保存已经测得的延迟，继续扫描时接着累积
+    fn save_results(&self) -> serde_json::Value {
+        serde_json::to_value(&self.stats).unwrap()
+    }
+
+    fn load_results(&mut self, results: serde_json::Value) -> serde_json::Result<()> {
+        if !results.is_null() {
+            self.stats = serde_json::from_value(results)?;
+        }
+        Ok(())
+    }
 }
 
 // 笔记
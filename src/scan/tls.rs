@@ -0,0 +1,354 @@
+//! TLS scan sending a ClientHello and parsing the server response, without completing the handshake
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use io_uring::{cqueue, opcode, squeue, types::Fd, Probe};
+use nix::{
+    errno::Errno,
+    sys::socket::{socket, AddressFamily, SockFlag, SockType, SockaddrLike},
+    unistd,
+};
+
+use crate::config::TlsScanOptions;
+use crate::cyclic::SplitMix64;
+use crate::result::{ScanResult, ScanType, State};
+use crate::ring::{BufferDirection, BufferInfo, EntryInfo, RingAllocator};
+use crate::scan::{
+    check_op_supported, socket_addr, PushError, RawFd, Scan, SockaddrStorage, SubmissionQueue,
+    Timeouts,
+};
+use crate::tls;
+
+/// Stop reading after this many bytes, certificate chains are well below that
+const MAX_RESPONSE_SIZE: usize = 64 * 1024;
+
+pub struct ScanTls {
+    sni: Option<String>,
+    alpn: Vec<String>,
+    max_version: u16,
+    /// Generates the random parts of each ClientHello
+    rng: SplitMix64,
+    /// In progress targets, by socket
+    targets: HashMap<RawFd, Target>,
+    /// Sockets that need another read to be pushed
+    pending_reads: Vec<RawFd>,
+}
+
+/// State of a single target
+struct Target {
+    addr: Rc<SockaddrStorage>,
+    result: ScanResult,
+    /// Everything received so far, records and handshake messages can span several reads
+    response: Vec<u8>,
+}
+
+/// Describes what scan step does an entry do
+#[derive(Debug)]
+enum EntryStep {
+    Connect = 0,
+    ConnectTimeout,
+    Send,
+    SendTimeout,
+    Recv,
+    RecvTimeout,
+}
+
+impl From<u8> for EntryStep {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => Self::Connect,
+            1 => Self::ConnectTimeout,
+            2 => Self::Send,
+            3 => Self::SendTimeout,
+            4 => Self::Recv,
+            5 => Self::RecvTimeout,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ScanTls {
+    pub fn new(opts: &TlsScanOptions) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Self {
+            sni: opts.sni.clone(),
+            alpn: opts.alpn.clone(),
+            max_version: if opts.max_version == "1.2" {
+                tls::TLS12
+            } else {
+                tls::TLS13
+            },
+            rng: SplitMix64(now.as_nanos() as u64),
+            targets: HashMap::new(),
+            pending_reads: Vec::new(),
+        }
+    }
+
+    fn client_hello(&mut self) -> Vec<u8> {
+        tls::client_hello(
+            self.sni.as_deref(),
+            &self.alpn,
+            self.max_version,
+            self.rng.next(),
+        )
+    }
+
+    /// Close socket and report what was parsed from the response
+    fn finish_target(&mut self, fd: RawFd) -> Option<ScanResult> {
+        let mut target = self.targets.remove(&fd)?;
+        unistd::close(fd).unwrap();
+        let flight = tls::parse_server_flight(&target.response);
+        if !target.response.is_empty() {
+            target.result.banner = Some(target.response);
+        }
+        target.result.matched = flight.info.is_some();
+        target.result.tls = flight.info;
+        Some(target.result)
+    }
+
+    /// Build a read op, and its timeout
+    fn recv_ops(
+        sckt: RawFd,
+        addr: &Rc<SockaddrStorage>,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> [squeue::Entry; 2] {
+        let rx_buffer = allocator.alloc_buf(BufferDirection::RX, None);
+        let op_recv_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(addr),
+                step: EntryStep::Recv as u8,
+                buf: Some(BufferInfo {
+                    idx: rx_buffer.idx,
+                    direction: BufferDirection::RX,
+                }),
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_recv = opcode::ReadFixed::new(
+            Fd(sckt),
+            rx_buffer.iov.iov_base.cast::<u8>(),
+            rx_buffer.iov.iov_len as u32,
+            rx_buffer.idx as u16,
+        )
+        .build()
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(op_recv_idx);
+
+        let entry_recv_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(addr),
+                step: EntryStep::RecvTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_recv_timeout = opcode::LinkTimeout::new(&timeouts.read)
+            .build()
+            .user_data(entry_recv_timeout_idx);
+
+        [op_recv, op_recv_timeout]
+    }
+}
+
+impl Scan for ScanTls {
+    fn check_supported(&self, probe: &Probe) -> bool {
+        check_op_supported(probe, opcode::Connect::CODE, "connect") &&
+        check_op_supported(probe, opcode::LinkTimeout::CODE, "link timeout") &&
+        check_op_supported(probe, opcode::WriteFixed::CODE, "write fixed") &&
+        check_op_supported(probe, opcode::ReadFixed::CODE, "read fixed")
+    }
+
+    fn max_tx_size(&mut self) -> Option<usize> {
+        // Only the random bytes change between targets, all ClientHellos have the same size
+        Some(self.client_hello().len())
+    }
+
+    fn ops_per_ip(&self) -> usize {
+        6
+    }
+
+    fn step_name(&self, step: u8) -> String {
+        format!("{:?}", EntryStep::from(step))
+    }
+
+    fn process_completed_entry(
+        &mut self,
+        cq_entry: &cqueue::Entry,
+        entry_info: &EntryInfo,
+        ring_allocator: &RingAllocator,
+    ) -> Option<ScanResult> {
+        let step = EntryStep::from(entry_info.step);
+        let errno = Errno::from_i32(-cq_entry.result());
+        log::debug!(
+            "op #{} ({:?} {}) returned {} ({:?})",
+            cq_entry.user_data(),
+            step,
+            entry_info.ip,
+            cq_entry.result(),
+            errno
+        );
+        match step {
+            EntryStep::Connect => {
+                if let Some(target) = self.targets.get_mut(&entry_info.fd) {
+                    target.result.state = State::from_connect(cq_entry.result());
+                    if target.result.state == State::Open {
+                        target.result.latency = Some(entry_info.start.elapsed());
+                    }
+                }
+                None
+            }
+            EntryStep::Recv => {
+                let ret = cq_entry.result();
+                if ret <= 0 {
+                    // EOF, error, timeout, or canceled because a previous step failed
+                    return self.finish_target(entry_info.fd);
+                }
+                let target = self.targets.get_mut(&entry_info.fd)?;
+                let buf = ring_allocator.get_buf(entry_info.buf.as_ref().unwrap().idx);
+                target.response.extend_from_slice(&buf[..ret as usize]);
+                if target.response.len() >= MAX_RESPONSE_SIZE
+                    || tls::parse_server_flight(&target.response).complete
+                {
+                    self.finish_target(entry_info.fd)
+                } else {
+                    self.pending_reads.push(entry_info.fd);
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn push_scan_ops(
+        &mut self,
+        sckt: RawFd,
+        addr: &SockaddrStorage,
+        squeue: &mut SubmissionQueue,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> Result<usize, PushError> {
+        let addr = Rc::new(addr.to_owned());
+
+        let entry_connect_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Connect as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_connect = opcode::Connect::new(Fd(sckt), addr.as_ptr(), addr.len())
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_connect_idx);
+
+        let entry_connect_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::ConnectTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_connect_timeout = opcode::LinkTimeout::new(&timeouts.connect)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_connect_timeout_idx);
+
+        let hello = self.client_hello();
+        let tx_buffer = allocator.alloc_buf(BufferDirection::TX, Some(&hello));
+        let op_send_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::Send as u8,
+                buf: Some(BufferInfo {
+                    idx: tx_buffer.idx,
+                    direction: BufferDirection::TX,
+                }),
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_send = opcode::WriteFixed::new(
+            Fd(sckt),
+            tx_buffer.iov.iov_base.cast::<u8>(),
+            hello.len() as u32,
+            tx_buffer.idx as u16,
+        )
+        .build()
+        .flags(squeue::Flags::IO_LINK)
+        .user_data(op_send_idx);
+
+        let entry_send_timeout_idx = allocator
+            .alloc_entry(EntryInfo {
+                ip: Rc::clone(&addr),
+                step: EntryStep::SendTimeout as u8,
+                buf: None,
+                fd: sckt,
+                start: Instant::now(),
+            })
+            .unwrap();
+        let op_send_timeout = opcode::LinkTimeout::new(&timeouts.write)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(entry_send_timeout_idx);
+
+        let [op_recv, op_recv_timeout] = Self::recv_ops(sckt, &addr, allocator, timeouts);
+
+        self.targets.insert(
+            sckt,
+            Target {
+                result: ScanResult::new(socket_addr(&addr), ScanType::Tls),
+                addr,
+                response: Vec::new(),
+            },
+        );
+
+        let ops = [
+            op_connect,
+            op_connect_timeout,
+            op_send,
+            op_send_timeout,
+            op_recv,
+            op_recv_timeout,
+        ];
+        unsafe {
+            squeue.push_multiple(&ops).expect("Failed to push ops");
+        }
+        Ok(ops.len())
+    }
+
+    fn push_followup_ops(
+        &mut self,
+        squeue: &mut SubmissionQueue,
+        allocator: &mut RingAllocator,
+        timeouts: &Timeouts,
+    ) -> usize {
+        let mut count = 0;
+        while let Some(&sckt) = self.pending_reads.last() {
+            if !allocator.has_free_entry_count(2) || squeue.capacity() - squeue.len() < 2 {
+                break;
+            }
+            self.pending_reads.pop();
+            let addr = Rc::clone(&self.targets[&sckt].addr);
+            let ops = Self::recv_ops(sckt, &addr, allocator, timeouts);
+            unsafe {
+                squeue.push_multiple(&ops).expect("Failed to push ops");
+            }
+            count += ops.len();
+        }
+        count
+    }
+
+    fn socket(&self, family: AddressFamily) -> RawFd {
+        socket(family, SockType::Stream, SockFlag::empty(), None)
+            .expect("Failed to create TCP socket")
+    }
+}
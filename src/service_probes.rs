@@ -0,0 +1,564 @@
+//! nmap-service-probes 格式的服务指纹库：解析其中的 TCP 探测和匹配规则，用服务端的响应识别服务、产品、版本和 CPE
+//!
+//! 格式见 https://nmap.org/book/vscan-fileformat.html
+
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use regex::bytes::{Captures, Regex, RegexBuilder};
+
+use crate::result::Service;
+
+/// 指纹库中的全部 TCP 探测
+#[derive(Debug)]
+pub struct ServiceProbes {
+    probes: Vec<ServiceProbe>,
+    null: Option<usize>, // 不发送数据、只等待 banner 的 NULL 探测
+    skipped: usize,      // 正则表达式不被 regex 支持而跳过的规则数，例如使用了反向引用的规则
+}
+
+/// 一个探测：发送的数据，以及用于识别响应的规则
+#[derive(Debug)]
+pub struct ServiceProbe {
+    pub name: String,
+    pub payload: Vec<u8>,
+    ports: Vec<RangeInclusive<u16>>,
+    rarity: u8,
+    fallback: Vec<usize>, // 本探测的规则都不匹配时，继续尝试这些探测的规则
+    matches: Vec<Match>,
+}
+
+/// match 或 softmatch 规则
+#[derive(Debug)]
+struct Match {
+    service: String,
+    regex: Regex,
+    soft: bool, // softmatch 只确定服务，没有版本信息
+    product: Option<String>,
+    version: Option<String>,
+    info: Option<String>,
+    hostname: Option<String>,
+    os: Option<String>,
+    device: Option<String>,
+    cpe: Vec<String>,
+}
+
+impl ServiceProbes {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        // 默认路径在没有安装 nmap 时不存在，错误信息中带上路径
+        let text = fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{:?}: {}", path, e)))?;
+        let probes = Self::parse(&String::from_utf8_lossy(&text))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}: {}", path, e)))?;
+        log::info!(
+            "Loaded {} TCP probes from {:?}, skipped {} rules with unsupported regexes",
+            probes.probes.len(),
+            path,
+            probes.skipped
+        );
+        Ok(probes)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut probes: Vec<ServiceProbe> = Vec::new();
+        let mut fallbacks: Vec<Vec<String>> = Vec::new();
+        let mut skipped = 0;
+        // 当前探测是否为 TCP 探测，UDP 探测之后的指令都属于它，一起忽略
+        let mut in_tcp_probe = None;
+        for (i, line) in text.lines().enumerate() {
+            let err = |e: &str| format!("line {}: {}", i + 1, e);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (directive, args) = line.split_once(' ').unwrap_or((line, ""));
+            let args = args.trim_start();
+            if directive == "Probe" {
+                let mut parts = args.splitn(3, ' ');
+                let (protocol, name, payload) = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(protocol), Some(name), Some(payload)) => (protocol, name, payload),
+                    _ => return Err(err("invalid Probe")),
+                };
+                in_tcp_probe = Some(protocol == "TCP");
+                if protocol != "TCP" {
+                    continue;
+                }
+                let payload = payload
+                    .strip_prefix('q')
+                    .and_then(delimited)
+                    .ok_or_else(|| err("invalid probe string"))?
+                    .0;
+                probes.push(ServiceProbe {
+                    name: name.to_string(),
+                    payload: unescape(payload).map_err(|e| err(&e))?,
+                    ports: Vec::new(),
+                    rarity: 1,
+                    fallback: Vec::new(),
+                    matches: Vec::new(),
+                });
+                fallbacks.push(Vec::new());
+                continue;
+            }
+            match in_tcp_probe {
+                Some(true) => {}
+                Some(false) => continue,
+                None if directive == "Exclude" => continue,
+                None => return Err(err("directive before the first Probe")),
+            }
+            let probe = probes.last_mut().unwrap();
+            match directive {
+                "match" | "softmatch" => match parse_match(args, directive == "softmatch") {
+                    Ok(Some(m)) => probe.matches.push(m),
+                    Ok(None) => skipped += 1,
+                    Err(e) => return Err(err(&e)),
+                },
+                "ports" => probe.ports = parse_ports(args).ok_or_else(|| err("invalid ports"))?,
+                "rarity" => probe.rarity = args.parse().map_err(|_| err("invalid rarity"))?,
+                "fallback" => {
+                    *fallbacks.last_mut().unwrap() =
+                        args.split(',').map(|s| s.trim().to_string()).collect()
+                }
+                // TLS 端口、等待时间等不影响识别
+                "sslports" | "totalwaitms" | "tcpwrappedms" | "Exclude" => {}
+                _ => return Err(err(&format!("unknown directive {:?}", directive))),
+            }
+        }
+
+        // fallback 可能引用后面定义的探测，全部解析完再转换成下标，找不到的忽略
+        for (i, names) in fallbacks.into_iter().enumerate() {
+            probes[i].fallback = names
+                .iter()
+                .filter_map(|name| probes.iter().position(|p| &p.name == name))
+                .collect();
+        }
+        let null = probes.iter().position(|p| p.payload.is_empty());
+        Ok(Self {
+            probes,
+            null,
+            skipped,
+        })
+    }
+
+    pub fn get(&self, idx: usize) -> &ServiceProbe {
+        &self.probes[idx]
+    }
+
+    pub fn null(&self) -> Option<usize> {
+        self.null
+    }
+
+    // 全部探测中最长的数据，用于预先分配发送缓冲区
+    pub fn max_payload_len(&self) -> usize {
+        self.probes.iter().map(|p| p.payload.len()).max().unwrap_or(0)
+    }
+
+    // 连接建立后的 NULL 探测之后依次发送的探测：先是 ports 中包含该端口的，再是 rarity 不超过 intensity 的，
+    // 和 nmap 一样，不论 intensity 是多少都会发送端口对应的探测
+    pub fn probes_for_port(&self, port: u16, intensity: u8) -> Vec<usize> {
+        let non_null = || (0..self.probes.len()).filter(|&i| !self.probes[i].payload.is_empty());
+        let for_port = |i: &usize| self.probes[*i].ports.iter().any(|r| r.contains(&port));
+        non_null()
+            .filter(for_port)
+            .chain(non_null().filter(|i| !for_port(i) && self.probes[*i].rarity <= intensity))
+            .collect()
+    }
+
+    // 用探测的规则识别响应，然后是 fallback 探测和 NULL 探测的规则，优先返回 match 的结果，其次是 softmatch
+    pub fn match_response(&self, probe: usize, response: &[u8]) -> Option<Service> {
+        let mut candidates = vec![probe];
+        candidates.extend(&self.probes[probe].fallback);
+        candidates.extend(self.null);
+        let mut soft = None;
+        for m in candidates.iter().flat_map(|&i| &self.probes[i].matches) {
+            let Some(captures) = m.regex.captures(response) else {
+                continue;
+            };
+            if !m.soft {
+                return Some(m.service(&captures));
+            }
+            soft.get_or_insert_with(|| m.service(&captures));
+        }
+        soft
+    }
+}
+
+impl Match {
+    // 把版本信息模板中的 $1 等替换成捕获的内容
+    fn service(&self, captures: &Captures) -> Service {
+        let field = |template: &Option<String>| {
+            template
+                .as_deref()
+                .map(|t| expand(t, captures))
+                .filter(|s| !s.is_empty())
+        };
+        Service {
+            name: self.service.clone(),
+            product: field(&self.product),
+            version: field(&self.version),
+            info: field(&self.info),
+            hostname: field(&self.hostname),
+            os: field(&self.os),
+            device: field(&self.device),
+            cpe: self
+                .cpe
+                .iter()
+                .map(|t| format!("cpe:/{}", expand(t, captures)))
+                .collect(),
+            soft: self.soft,
+        }
+    }
+}
+
+// 解析 match 规则中服务名之后的部分，例如 `ssh m|^SSH-([\d.]+)-OpenSSH_(\S+)|s p/OpenSSH/ v/$2/`
+// 正则表达式不被支持时返回 None
+fn parse_match(args: &str, soft: bool) -> Result<Option<Match>, String> {
+    let (service, rest) = args.split_once(' ').ok_or("missing pattern")?;
+    let (pattern, rest) = rest
+        .trim_start()
+        .strip_prefix('m')
+        .and_then(delimited)
+        .ok_or("invalid pattern")?;
+    let flags_end = rest.find(' ').unwrap_or(rest.len());
+    let (flags, mut rest) = rest.split_at(flags_end);
+
+    // nmap 使用 PCRE，匹配的是字节而不是 UTF-8 字符
+    let regex = RegexBuilder::new(&translate_pattern(pattern))
+        .unicode(false)
+        .case_insensitive(flags.contains('i'))
+        .dot_matches_new_line(flags.contains('s'))
+        .build();
+    let Ok(regex) = regex else {
+        log::debug!("Skipping unsupported regex {:?}", pattern);
+        return Ok(None);
+    };
+
+    let mut m = Match {
+        service: service.to_string(),
+        regex,
+        soft,
+        product: None,
+        version: None,
+        info: None,
+        hostname: None,
+        os: None,
+        device: None,
+        cpe: Vec::new(),
+    };
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(cpe) = rest.strip_prefix("cpe:") {
+            let (value, r) = delimited(cpe).ok_or("invalid cpe")?;
+            m.cpe.push(value.to_string());
+            // 'a' 标志表示这是应用的 CPE，输出中没有区别
+            rest = r.strip_prefix('a').unwrap_or(r);
+            continue;
+        }
+        let field = match rest.as_bytes()[0] {
+            b'p' => &mut m.product,
+            b'v' => &mut m.version,
+            b'i' => &mut m.info,
+            b'h' => &mut m.hostname,
+            b'o' => &mut m.os,
+            b'd' => &mut m.device,
+            _ => return Err(format!("invalid version info {:?}", rest)),
+        };
+        let (value, r) = delimited(&rest[1..]).ok_or("invalid version info")?;
+        *field = Some(value.to_string());
+        rest = r;
+    }
+    Ok(Some(m))
+}
+
+// 以第一个字符为分隔符，返回到下一个分隔符之间的内容和剩余的部分
+fn delimited(s: &str) -> Option<(&str, &str)> {
+    let delim = s.chars().next()?;
+    let s = &s[delim.len_utf8()..];
+    let end = s.find(delim)?;
+    Some((&s[..end], &s[end + delim.len_utf8()..]))
+}
+
+// 把 regex 不支持的 PCRE 语法换成等价的写法
+fn translate_pattern(pattern: &str) -> String {
+    let mut translated = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            translated.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => translated.push_str("\\x00"),
+            Some('Z') => translated.push_str("\\z"),
+            Some(c) => {
+                translated.push('\\');
+                translated.push(c);
+            }
+            None => translated.push('\\'),
+        }
+    }
+    translated
+}
+
+// 探测数据中的转义，和 C 字符串相同
+fn unescape(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut utf8 = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+            continue;
+        }
+        let b = match chars.next() {
+            Some('0') => 0,
+            Some('a') => 0x07,
+            Some('b') => 0x08,
+            Some('f') => 0x0c,
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('v') => 0x0b,
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err(format!("invalid escape \\x{} in probe string", hex));
+                }
+                u8::from_str_radix(&hex, 16).unwrap()
+            }
+            Some(c) if c.is_ascii() => c as u8,
+            _ => return Err("invalid escape in probe string".to_string()),
+        };
+        bytes.push(b);
+    }
+    Ok(bytes)
+}
+
+// 端口列表，例如 `1,7,9,13,21-23`
+fn parse_ports(s: &str) -> Option<Vec<RangeInclusive<u16>>> {
+    s.split(',')
+        .map(|part| {
+            let part = part.trim();
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            Some(first.parse().ok()?..=last.parse().ok()?)
+        })
+        .collect()
+}
+
+// 展开版本信息模板中的 $N、$P(N)、$SUBST(N,"from","to") 和 $I(N,">")，其他内容原样保留
+fn expand(template: &str, captures: &Captures) -> String {
+    let group = |n: u8| {
+        captures
+            .get(usize::from(n - b'0'))
+            .map_or(&[][..], |m| m.as_bytes())
+    };
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let bytes = rest.as_bytes();
+        if bytes.len() > 1 && bytes[1].is_ascii_digit() {
+            expanded.push_str(&String::from_utf8_lossy(group(bytes[1])));
+            rest = &rest[2..];
+            continue;
+        }
+        let Some(helper) = Helper::parse(rest) else {
+            expanded.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let value = group(helper.group);
+        match (helper.func, helper.args.as_slice()) {
+            ("P", []) => expanded.extend(
+                value
+                    .iter()
+                    .filter(|b| b.is_ascii_graphic() || **b == b' ')
+                    .map(|&b| b as char),
+            ),
+            ("SUBST", [from, to]) => {
+                expanded.push_str(&String::from_utf8_lossy(value).replace(from, to))
+            }
+            ("I", [endian]) if value.len() <= 8 => {
+                let mut n = 0u64;
+                let mut push = |b: &u8| n = (n << 8) | u64::from(*b);
+                if *endian == "<" {
+                    value.iter().rev().for_each(&mut push);
+                } else {
+                    value.iter().for_each(&mut push);
+                }
+                expanded.push_str(&n.to_string());
+            }
+            _ => expanded.push_str(&rest[..rest.len() - helper.rest.len()]),
+        }
+        rest = helper.rest;
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+// 版本信息模板中的 `$NAME(N,"arg",...)`
+struct Helper<'a> {
+    func: &'a str,
+    group: u8, // 分组号的数字字符
+    args: Vec<&'a str>,
+    rest: &'a str, // 模板中剩余的部分
+}
+
+impl<'a> Helper<'a> {
+    fn parse(s: &'a str) -> Option<Self> {
+        let s = s.strip_prefix('$')?;
+        let (func, s) = s.split_at(s.find('(')?);
+        if func.is_empty() || !func.bytes().all(|b| b.is_ascii_uppercase()) {
+            return None;
+        }
+        let s = &s[1..];
+        let group = *s.as_bytes().first().filter(|b| b.is_ascii_digit())?;
+        let mut s = &s[1..];
+        let mut args = Vec::new();
+        while let Some(r) = s.strip_prefix(',') {
+            let r = r.strip_prefix('"')?;
+            let end = r.find('"')?;
+            args.push(&r[..end]);
+            s = &r[end + 1..];
+        }
+        Some(Self {
+            func,
+            group,
+            args,
+            rest: s.strip_prefix(')')?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBES: &str = r#"
+# Example nmap-service-probes
+Exclude T:9100-9107
+
+Probe TCP NULL q||
+totalwaitms 6000
+match ftp m/^220 ProFTPD (\d\S+) Server/ p/ProFTPD/ v/$1/ cpe:/a:proftpd:proftpd:$1/
+match ssh m|^SSH-([\d.]+)-OpenSSH_([\w._-]+)[ -]{1,2}Debian[ -_]([^\r\n]+)\r?\n| p/OpenSSH/ v/$2 Debian $3/ i/protocol $1/ o/Linux/ cpe:/a:openbsd:openssh:$2/ cpe:/o:debian:debian_linux/ cpe:/o:linux:linux_kernel/a
+match mysql m|^.\0\0\0\x0a([\w._-]+)\0| p/MySQL/ v/$1/
+match backref m/^(a)\1/ p/Unsupported/
+softmatch ftp m/^220[- ]/i
+
+Probe UDP DNSStatusRequest q|\0\0\x10\0\0\0\0\0\0\0\0\0|
+ports 53
+match dns m|^\0\0\x90| p/ISC BIND/
+
+Probe TCP GetRequest q|GET / HTTP/1.0\r\n\r\n|
+rarity 1
+ports 80-85,8080
+match http m|^HTTP/1\.[01] \d\d\d .*\r\nServer: nginx/([\d.]+)\r\n|s p/nginx/ v/$1/ cpe:/a:igor_sysoev:nginx:$1/
+softmatch http m|^HTTP/1\.[01] \d\d\d|
+
+Probe TCP RTSPRequest q|OPTIONS / RTSP/1.0\r\n\r\n|
+rarity 5
+ports 554
+fallback GetRequest
+
+Probe TCP redis-server q|*1\r\n$4\r\ninfo\r\n|
+rarity 8
+ports 6379
+match redis m|^\$\d+\r\n# Server\r\nredis_version:([\d.]+)\r\n| p/Redis key-value store/ v/$1/
+"#;
+
+    #[test]
+    fn test_parse() {
+        let probes = ServiceProbes::parse(PROBES).unwrap();
+        let names: Vec<_> = probes.probes.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["NULL", "GetRequest", "RTSPRequest", "redis-server"]);
+        assert_eq!(probes.null, Some(0));
+        assert_eq!(probes.skipped, 1);
+        assert_eq!(probes.get(1).payload, b"GET / HTTP/1.0\r\n\r\n");
+        assert_eq!(probes.get(3).payload, b"*1\r\n$4\r\ninfo\r\n");
+        assert_eq!(probes.get(2).fallback, [1]);
+        assert_eq!(probes.max_payload_len(), 22);
+        assert!(ServiceProbes::parse("match ssh m|^SSH|").is_err());
+        assert!(ServiceProbes::parse("Probe TCP NULL q||\nmatch ssh m|^SSH").is_err());
+        assert!(ServiceProbes::parse("Probe TCP NULL q||\nfoo bar").is_err());
+    }
+
+    #[test]
+    fn test_probes_for_port() {
+        let probes = ServiceProbes::parse(PROBES).unwrap();
+        assert_eq!(probes.probes_for_port(22, 7), [1, 2]);
+        assert_eq!(probes.probes_for_port(554, 7), [2, 1]);
+        assert_eq!(probes.probes_for_port(6379, 0), [3]);
+        assert_eq!(probes.probes_for_port(6379, 9), [3, 1, 2]);
+    }
+
+    #[test]
+    fn test_match_response() {
+        let probes = ServiceProbes::parse(PROBES).unwrap();
+        let ssh = probes
+            .match_response(0, b"SSH-2.0-OpenSSH_8.4p1 Debian-5+deb11u1\r\n")
+            .unwrap();
+        assert_eq!(
+            ssh,
+            Service {
+                name: "ssh".to_string(),
+                product: Some("OpenSSH".to_string()),
+                version: Some("8.4p1 Debian 5+deb11u1".to_string()),
+                info: Some("protocol 2.0".to_string()),
+                hostname: None,
+                os: Some("Linux".to_string()),
+                device: None,
+                cpe: vec![
+                    "cpe:/a:openbsd:openssh:8.4p1".to_string(),
+                    "cpe:/o:debian:debian_linux".to_string(),
+                    "cpe:/o:linux:linux_kernel".to_string(),
+                ],
+                soft: false,
+            }
+        );
+
+        let mysql = probes
+            .match_response(0, b"J\0\0\0\x0a8.0.36\0\x08\0\0\0")
+            .unwrap();
+        assert_eq!(mysql.version.as_deref(), Some("8.0.36"));
+
+        // softmatch 只在没有 match 时使用，匹配不区分大小写
+        let ftp = probes.match_response(0, b"220 ProFTPD 1.3.5 Server ready\r\n").unwrap();
+        assert_eq!((ftp.version.as_deref(), ftp.soft), (Some("1.3.5"), false));
+        let ftp = probes.match_response(0, b"220-vsFTPd\r\n").unwrap();
+        assert_eq!((ftp.name.as_str(), ftp.product, ftp.soft), ("ftp", None, true));
+
+        let nginx = b"HTTP/1.1 200 OK\r\nServer: nginx/1.18.0\r\n\r\n";
+        let http = probes.match_response(1, nginx).unwrap();
+        assert_eq!(http.cpe, ["cpe:/a:igor_sysoev:nginx:1.18.0"]);
+        // RTSPRequest 的响应用 GetRequest 的规则识别
+        assert_eq!(probes.match_response(2, nginx), Some(http));
+        // GetRequest 的响应也会用 NULL 探测的规则识别
+        assert_eq!(
+            probes.match_response(1, b"SSH-2.0-OpenSSH_8.4p1 Debian-5\r\n").unwrap().name,
+            "ssh"
+        );
+        assert_eq!(probes.match_response(3, b"-ERR unknown command\r\n"), None);
+    }
+
+    #[test]
+    fn test_expand() {
+        let regex = Regex::new(r"(?-u)^v=(\S+) host=(\S+) n=(..)").unwrap();
+        let captures = regex.captures(b"v=1_2 host=a\x01b n=\x01\x02").unwrap();
+        assert_eq!(expand("$1 ($2)", &captures), "1_2 (a\u{1}b)");
+        assert_eq!(expand("$P(2)", &captures), "ab");
+        assert_eq!(expand(r#"$SUBST(1,"_",".")"#, &captures), "1.2");
+        assert_eq!(expand(r#"$I(3,">") $I(3,"<")"#, &captures), "258 513");
+        assert_eq!(expand("$$ $X(1) $9", &captures), "$$ $X(1) ");
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(r"a\0\r\n\t\x41\\\|").unwrap(), b"a\0\r\n\tA\\|");
+        assert!(unescape(r"\x4").is_err());
+    }
+}
@@ -0,0 +1,41 @@
+//! SIGINT/SIGTERM 处理：第一次收到信号时停止扫描新的目标，等待进行中的操作完成后输出结果，
+//! 第二次收到信号时立即退出
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use nix::libc;
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+// 第一次收到的信号，0 表示还没有收到信号
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn handle_signal(sig: libc::c_int) {
+    if RECEIVED
+        .compare_exchange(0, sig, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        // 信号处理函数中只能调用异步信号安全的函数
+        unsafe { libc::_exit(128 + sig) };
+    }
+}
+
+// 安装 SIGINT 和 SIGTERM 的处理函数
+pub fn install() -> nix::Result<()> {
+    let action = SigAction::new(
+        SigHandler::Handler(handle_signal),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    for sig in [Signal::SIGINT, Signal::SIGTERM] {
+        unsafe { sigaction(sig, &action)? };
+    }
+    Ok(())
+}
+
+// 返回第一次收到的信号
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        sig => Some(sig),
+    }
+}
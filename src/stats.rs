@@ -0,0 +1,153 @@
+//! 多轮测速的延迟统计
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// 单个目标在多轮（`--time`）测速中累积的延迟样本
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LatencyStats {
+    samples: Vec<Duration>, // 每轮成功连接的延迟，按轮次顺序保存
+    failed: usize,          // 连接失败（超时、拒绝等）的轮数
+}
+
+impl LatencyStats {
+    // 记录一轮成功连接的延迟
+    pub fn record_success(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+
+    // 记录一轮失败的连接
+    pub fn record_failure(&mut self) {
+        self.failed += 1;
+    }
+
+    // 总共测试的轮数
+    pub fn sent(&self) -> usize {
+        self.samples.len() + self.failed
+    }
+
+    // 成功连接的轮数
+    pub fn received(&self) -> usize {
+        self.samples.len()
+    }
+
+    // 丢包率，取值范围 0 到 100
+    pub fn loss_percent(&self) -> f64 {
+        match self.sent() {
+            0 => 0.0,
+            sent => self.failed as f64 * 100.0 / sent as f64,
+        }
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+
+    // 中位数，样本数为偶数时取中间两个值的平均
+    pub fn median(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let mid = sorted.len() / 2;
+        if sorted.len() % 2 == 0 {
+            Some((sorted[mid - 1] + sorted[mid]) / 2)
+        } else {
+            Some(sorted[mid])
+        }
+    }
+
+    // 抖动：相邻两轮延迟差值绝对值的平均，少于两个样本时无法计算
+    pub fn jitter(&self) -> Option<Duration> {
+        if self.samples.len() < 2 {
+            return None;
+        }
+        let total: Duration = self
+            .samples
+            .windows(2)
+            .map(|w| {
+                if w[1] > w[0] {
+                    w[1] - w[0]
+                } else {
+                    w[0] - w[1]
+                }
+            })
+            .sum();
+        Some(total / (self.samples.len() - 1) as u32)
+    }
+}
+
+/// 将可选的时长格式化为毫秒，没有数据时输出 `-`
+pub fn format_ms(d: Option<Duration>) -> String {
+    match d {
+        Some(d) => format!("{:.1}ms", d.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(v: u64) -> Duration {
+        Duration::from_millis(v)
+    }
+
+    #[test]
+    fn test_latency_stats_empty() {
+        let stats = LatencyStats::default();
+        assert_eq!(stats.sent(), 0);
+        assert_eq!(stats.loss_percent(), 0.0);
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.avg(), None);
+        assert_eq!(stats.median(), None);
+        assert_eq!(stats.jitter(), None);
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        let mut stats = LatencyStats::default();
+        stats.record_success(ms(10));
+        stats.record_failure();
+        stats.record_success(ms(30));
+        stats.record_success(ms(20));
+
+        assert_eq!(stats.sent(), 4);
+        assert_eq!(stats.received(), 3);
+        assert_eq!(stats.loss_percent(), 25.0);
+        assert_eq!(stats.min(), Some(ms(10)));
+        assert_eq!(stats.max(), Some(ms(30)));
+        assert_eq!(stats.avg(), Some(ms(20)));
+        assert_eq!(stats.median(), Some(ms(20)));
+        // |30 - 10| 和 |20 - 30| 的平均
+        assert_eq!(stats.jitter(), Some(ms(15)));
+    }
+
+    #[test]
+    fn test_latency_stats_median_even() {
+        let mut stats = LatencyStats::default();
+        for v in [40, 10, 20, 30] {
+            stats.record_success(ms(v));
+        }
+        assert_eq!(stats.median(), Some(ms(25)));
+    }
+
+    #[test]
+    fn test_format_ms() {
+        assert_eq!(format_ms(Some(Duration::from_micros(12_345))), "12.3ms");
+        assert_eq!(format_ms(None), "-");
+    }
+}
@@ -0,0 +1,136 @@
+//! 扫描结束时输出的统计汇总：目标数和速度、提交和完成的操作数，以及每个步骤的错误码分布
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::time::Instant;
+
+use nix::errno::Errno;
+
+use crate::result::{State, StateCounts};
+
+/// 整个扫描过程的计数，所有扫描类型通用
+pub struct ScanSummary {
+    start: Instant,
+    targets: u64,
+    states: StateCounts,
+    submitted: u64,
+    completed: u64,
+    peak_in_flight: usize,
+    // 按步骤统计完成的操作，步骤的名称由扫描类型决定
+    steps: BTreeMap<u8, StepStats>,
+}
+
+#[derive(Default)]
+struct StepStats {
+    ok: u64,
+    errors: BTreeMap<i32, u64>, // errno 和出现的次数
+}
+
+impl ScanSummary {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            targets: 0,
+            states: StateCounts::default(),
+            submitted: 0,
+            completed: 0,
+            peak_in_flight: 0,
+            steps: BTreeMap::new(),
+        }
+    }
+
+    // 一个目标扫描完成
+    pub fn add_target(&mut self, state: State) {
+        self.targets += 1;
+        self.states.add(state);
+    }
+
+    // 推入了新的操作，`in_flight` 为推入后进行中的操作数
+    pub fn add_submitted(&mut self, count: usize, in_flight: usize) {
+        self.submitted += count as u64;
+        self.peak_in_flight = self.peak_in_flight.max(in_flight);
+    }
+
+    // 一个操作完成，`ret` 为 CQE 的返回值，负数为 -errno
+    pub fn add_completed(&mut self, step: u8, ret: i32) {
+        self.completed += 1;
+        let stats = self.steps.entry(step).or_default();
+        if ret >= 0 {
+            stats.ok += 1;
+        } else {
+            *stats.errors.entry(-ret).or_default() += 1;
+        }
+    }
+
+    pub fn write(&self, out: &mut dyn Write, step_name: impl Fn(u8) -> String) -> io::Result<()> {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            self.targets as f64 / elapsed
+        } else {
+            0.0
+        };
+        writeln!(
+            out,
+            "Scanned {} targets in {:.2}s ({:.2} targets/s): {}",
+            self.targets, elapsed, rate, self.states
+        )?;
+        writeln!(
+            out,
+            "Operations: {} submitted, {} completed, peak {} in flight",
+            self.submitted, self.completed, self.peak_in_flight
+        )?;
+        for (step, stats) in &self.steps {
+            write!(out, "  {:<16}{:>10} ok", step_name(*step), stats.ok)?;
+            for (errno, count) in &stats.errors {
+                write!(out, ", {} {:?}", count, Errno::from_i32(*errno))?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nix::libc;
+
+    #[test]
+    fn test_scan_summary() {
+        let mut summary = ScanSummary::new();
+        summary.add_submitted(3, 3);
+        summary.add_submitted(3, 5);
+        summary.add_submitted(3, 4);
+        for ret in [
+            0,
+            -libc::ECONNREFUSED,
+            -libc::ECANCELED,
+            -libc::ECONNREFUSED,
+        ] {
+            summary.add_completed(0, ret);
+        }
+        summary.add_completed(2, 0);
+        summary.add_target(State::Open);
+        summary.add_target(State::Closed);
+
+        let mut out = Vec::new();
+        summary
+            .write(&mut out, |step| {
+                ["Connect", "ConnectTimeout", "Close"][step as usize].to_string()
+            })
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert!(lines[0].starts_with("Scanned 2 targets in "));
+        assert!(lines[0].ends_with(": 1 open, 1 closed, 0 filtered, 0 unreachable"));
+        assert_eq!(
+            lines[1..],
+            [
+                "Operations: 9 submitted, 5 completed, peak 5 in flight",
+                "  Connect                  1 ok, 2 ECONNREFUSED, 1 ECANCELED",
+                "  Close                    1 ok",
+            ]
+        );
+    }
+}
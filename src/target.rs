@@ -0,0 +1,728 @@
+//! 扫描目标的生成，支持 IPv4 和 IPv6，以及每个 IP 的多个端口
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+
+use ipnet::{IpAddrRange, IpNet, Ipv4AddrRange, Ipv4Net, Ipv4Subnets, Ipv6Net, Ipv6Subnets};
+use iprange::IpRange;
+
+use crate::cyclic::Cyclic;
+
+/// 收集全部的 CIDRs 后合并生成新的 CIDRs，顺便去重了
+#[derive(Clone, Default)]
+pub struct Targets {
+    v4: IpRange<Ipv4Net>,
+    v6: IpRange<Ipv6Net>,
+    ports: Vec<u16>,
+    // 以 `ip:port` 形式指定的目标，只扫描该端口
+    endpoints: Vec<SocketAddr>,
+    // 不扫描的网段
+    excluded_v4: IpRange<Ipv4Net>,
+    excluded_v6: IpRange<Ipv6Net>,
+    // finalize 之后按地址排序的网段，以及每个网段第一个地址在全部地址中的序号，用于按下标查找目标
+    blocks: Vec<(u128, IpNet)>,
+}
+
+/// 默认不扫描的保留、私有、组播和广播地址段
+const RESERVED_NETS: [&str; 29] = [
+    // https://www.iana.org/assignments/iana-ipv4-special-registry
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.88.99.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    // https://www.iana.org/assignments/iana-ipv6-special-registry
+    "::/128",
+    "::1/128",
+    "::/96",
+    "::ffff:0:0/96",
+    "64:ff9b:1::/48",
+    "100::/64",
+    "2001:10::/28",
+    "2001:db8::/32",
+    "3fff::/20",
+    "5f00::/16",
+    "fc00::/7",
+    "fe80::/10",
+    "fec0::/10",
+    "ff00::/8",
+];
+
+/// 目标文件中的一行
+#[derive(Debug, PartialEq)]
+enum TargetSpec {
+    Nets(Vec<IpNet>),
+    // `a-b` 范围拆分出的网段，与单个 IP 一样扫描其中的全部地址
+    Ranges(Vec<IpNet>),
+    Endpoints(Vec<SocketAddr>),
+}
+
+impl Targets {
+    pub fn new(ports: &[u16]) -> Self {
+        Self {
+            ports: ports.to_vec(),
+            ..Self::default()
+        }
+    }
+
+    // 与 Ipv4Net::hosts 一致，/31 和 /32 以外的 IPv4 网段不扫描网络地址和广播地址。
+    // 在合并之前去掉，单个 IP 和范围合并后可能恰好是对齐的网段，其中的地址都要扫描
+    pub fn add_net(&mut self, net: IpNet) {
+        match net {
+            IpNet::V4(net) if net.prefix_len() < 31 => {
+                let hosts = Ipv4Subnets::new(
+                    Ipv4Addr::from(u32::from(net.network()) + 1),
+                    Ipv4Addr::from(u32::from(net.broadcast()) - 1),
+                    0,
+                );
+                for net in hosts {
+                    self.v4.add(net);
+                }
+            }
+            net => self.add_range(net),
+        }
+    }
+
+    // 添加网段中的全部地址
+    fn add_range(&mut self, net: IpNet) {
+        match net {
+            IpNet::V4(net) => {
+                self.v4.add(net);
+            }
+            IpNet::V6(net) => {
+                self.v6.add(net);
+            }
+        }
+    }
+
+    pub fn add_endpoint(&mut self, addr: SocketAddr) {
+        self.endpoints.push(addr);
+    }
+
+    fn add_spec(&mut self, spec: TargetSpec) {
+        match spec {
+            TargetSpec::Nets(nets) => nets.into_iter().for_each(|n| self.add_net(n)),
+            TargetSpec::Ranges(nets) => nets.into_iter().for_each(|n| self.add_range(n)),
+            TargetSpec::Endpoints(addrs) => addrs.into_iter().for_each(|a| self.add_endpoint(a)),
+        }
+    }
+
+    pub fn exclude_net(&mut self, net: IpNet) {
+        match net {
+            IpNet::V4(net) => {
+                self.excluded_v4.add(net);
+            }
+            IpNet::V6(net) => {
+                self.excluded_v6.add(net);
+            }
+        }
+    }
+
+    // 排除列表中的 `ip:port` 会排除整个 IP
+    fn exclude_spec(&mut self, spec: TargetSpec) {
+        match spec {
+            TargetSpec::Nets(nets) | TargetSpec::Ranges(nets) => {
+                nets.into_iter().for_each(|n| self.exclude_net(n))
+            }
+            TargetSpec::Endpoints(addrs) => addrs
+                .into_iter()
+                .for_each(|a| self.exclude_net(IpNet::from(a.ip()))),
+        }
+    }
+
+    // 排除默认的保留地址段
+    pub fn exclude_reserved(&mut self) {
+        for net in RESERVED_NETS {
+            self.exclude_net(net.parse().unwrap());
+        }
+    }
+
+    // 从文件读取目标，路径为 `-` 时读取标准输入
+    pub fn load_file(&mut self, path: &Path) -> io::Result<()> {
+        read_target_file(path, |spec| self.add_spec(spec))
+    }
+
+    // 从文件读取排除列表，格式与目标文件相同
+    pub fn load_exclude_file(&mut self, path: &Path) -> io::Result<()> {
+        read_target_file(path, |spec| self.exclude_spec(spec))
+    }
+
+    #[cfg(test)]
+    fn load(&mut self, reader: impl BufRead, name: &str) -> io::Result<()> {
+        read_targets(reader, &name, |spec| self.add_spec(spec))
+    }
+
+    // 合并相邻和重叠的网段，从中减去排除的网段，并去掉已经被网段和端口列表覆盖的 `ip:port` 目标
+    // 返回被排除的目标数，添加完全部目标和排除网段后调用一次
+    pub fn finalize(&mut self) -> u128 {
+        self.v4.simplify();
+        self.v6.simplify();
+        self.dedup_endpoints();
+        let count = self.count();
+
+        self.excluded_v4.simplify();
+        self.excluded_v6.simplify();
+        self.v4 = self.v4.exclude(&self.excluded_v4);
+        self.v6 = self.v6.exclude(&self.excluded_v6);
+        let endpoints = std::mem::take(&mut self.endpoints);
+        self.endpoints = endpoints
+            .into_iter()
+            .filter(|addr| match addr.ip() {
+                IpAddr::V4(ip) => !self.excluded_v4.contains(&ip),
+                IpAddr::V6(ip) => !self.excluded_v6.contains(&ip),
+            })
+            .collect();
+        self.build_blocks();
+        count.saturating_sub(self.count())
+    }
+
+    fn build_blocks(&mut self) {
+        let mut v4: Vec<Ipv4Net> = self.v4.iter().collect();
+        v4.sort_unstable();
+        let mut v6: Vec<Ipv6Net> = self.v6.iter().collect();
+        v6.sort_unstable();
+        let mut offset = 0;
+        self.blocks = v4
+            .into_iter()
+            .map(IpNet::V4)
+            .chain(v6.into_iter().map(IpNet::V6))
+            .map(|net| {
+                let block = (offset, net);
+                offset = u128::saturating_add(offset, net_size(&net));
+                block
+            })
+            .collect();
+    }
+
+    // 去掉已经被网段和端口列表覆盖的 `ip:port` 目标
+    fn dedup_endpoints(&mut self) {
+        self.endpoints.sort_unstable();
+        self.endpoints.dedup();
+        let endpoints = std::mem::take(&mut self.endpoints);
+        self.endpoints = endpoints
+            .into_iter()
+            .filter(|addr| {
+                !self.ports.contains(&addr.port())
+                    || match addr.ip() {
+                        IpAddr::V4(ip) => !self.v4.contains(&ip),
+                        IpAddr::V6(ip) => !self.v6.contains(&ip),
+                    }
+            })
+            .collect();
+    }
+
+    // 目标 (IP, 端口) 的总数，IPv6 网段可能非常大，所以使用 u128 并在溢出时取最大值
+    pub fn count(&self) -> u128 {
+        let v4 = self.v4.iter().map(IpNet::V4);
+        let v6 = self.v6.iter().map(IpNet::V6);
+        v4.chain(v6)
+            .map(|net| net_size(&net))
+            .fold(0, u128::saturating_add)
+            .saturating_mul(self.ports.len() as u128)
+            .saturating_add(self.endpoints.len() as u128)
+    }
+
+    // 网段中的地址总数
+    fn host_count(&self) -> u128 {
+        self.blocks
+            .last()
+            .map_or(0, |(offset, net)| offset.saturating_add(net_size(net)))
+    }
+
+    // 依次遍历每个网段中的全部地址，每个地址再遍历全部端口，最后是 `ip:port` 目标
+    pub fn iter(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.blocks
+            .iter()
+            .flat_map(|(_, net)| match net {
+                IpNet::V4(net) => {
+                    IpAddrRange::from(Ipv4AddrRange::new(net.network(), net.broadcast()))
+                }
+                IpNet::V6(net) => IpAddrRange::from(net.hosts()),
+            })
+            .flat_map(move |ip| {
+                self.ports
+                    .iter()
+                    .map(move |port| SocketAddr::new(ip, *port))
+            })
+            .chain(self.endpoints.iter().copied())
+    }
+
+    // 按下标查找目标，与 iter 的顺序相同，finalize 之后才能调用
+    pub fn get(&self, index: u64) -> SocketAddr {
+        let index = index as u128;
+        let ports = self.ports.len() as u128;
+        let host_targets = self.host_count().saturating_mul(ports);
+        if index >= host_targets {
+            return self.endpoints[(index - host_targets) as usize];
+        }
+
+        let (host, port) = (index / ports, index % ports);
+        // 最后一个起始序号不大于 host 的网段
+        let block = self.blocks.partition_point(|(offset, _)| *offset <= host) - 1;
+        let (offset, net) = self.blocks[block];
+        let ip = match net {
+            IpNet::V4(net) => IpAddr::V4(Ipv4Addr::from(
+                u32::from(net.network()) + (host - offset) as u32,
+            )),
+            IpNet::V6(net) => {
+                IpAddr::V6(Ipv6Addr::from(u128::from(net.network()) + (host - offset)))
+            }
+        };
+        SocketAddr::new(ip, self.ports[port as usize])
+    }
+
+    // 以种子决定的随机顺序遍历全部目标，目标数超出 u64 时返回 None
+    pub fn iter_random(&self, seed: u64) -> Option<impl Iterator<Item = SocketAddr> + '_> {
+        let count = u64::try_from(self.count()).ok()?;
+        Some(Cyclic::new(count, seed)?.map(move |index| self.get(index)))
+    }
+}
+
+// 打开目标文件，路径为 `-` 时读取标准输入
+fn read_target_file(path: &Path, f: impl FnMut(TargetSpec)) -> io::Result<()> {
+    let name = path.display();
+    if path.as_os_str() == "-" {
+        read_targets(io::stdin().lock(), &name, f)
+    } else {
+        read_targets(BufReader::new(File::open(path)?), &name, f)
+    }
+}
+
+// 逐行读取目标，不需要把整个文件读入内存
+fn read_targets(
+    reader: impl BufRead,
+    name: &impl std::fmt::Display,
+    mut f: impl FnMut(TargetSpec),
+) -> io::Result<()> {
+    for (i, line) in reader.lines().enumerate() {
+        match parse_target_line(&line?) {
+            Ok(Some(spec)) => f(spec),
+            Ok(None) => {}
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", name, i + 1, e),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+// 解析目标文件中的一行，支持 CIDR、单个 IP、`a.b.c.d-e.f.g.h` 形式的范围以及 `ip:port`
+// `#` 之后为注释，每行只取第一列，所以也可以直接读取本程序输出的结果
+// 也支持 masscan 列表和 nmap grepable 格式的扫描结果，只取其中开放的端口
+fn parse_target_line(line: &str) -> Result<Option<TargetSpec>, String> {
+    if let Some(host) = line.strip_prefix("Host: ") {
+        return parse_nmap_grepable(host).map(Some);
+    }
+    let mut columns = line.split('#').next().unwrap().split_whitespace();
+    let token = match columns.next() {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+    if let "open" | "closed" | "banner" = token {
+        return parse_masscan_list(token, columns);
+    }
+
+    if token.contains('/') {
+        let net = token
+            .parse::<IpNet>()
+            .map_err(|e| format!("Invalid CIDR {:?}: {}", token, e))?;
+        return Ok(Some(TargetSpec::Nets(vec![net])));
+    }
+    if let Some((first, last)) = token.split_once('-') {
+        let parse_ip = |ip: &str| {
+            ip.parse::<IpAddr>()
+                .map_err(|e| format!("Invalid IP {:?}: {}", ip, e))
+        };
+        let nets: Vec<IpNet> = match (parse_ip(first)?, parse_ip(last)?) {
+            (IpAddr::V4(first), IpAddr::V4(last)) if first <= last => {
+                Ipv4Subnets::new(first, last, 0).map(IpNet::V4).collect()
+            }
+            (IpAddr::V6(first), IpAddr::V6(last)) if first <= last => {
+                Ipv6Subnets::new(first, last, 0).map(IpNet::V6).collect()
+            }
+            _ => return Err(format!("Invalid IP range {:?}", token)),
+        };
+        return Ok(Some(TargetSpec::Ranges(nets)));
+    }
+    if let Ok(ip) = token.parse::<IpAddr>() {
+        return Ok(Some(TargetSpec::Nets(vec![IpNet::from(ip)])));
+    }
+    if let Ok(addr) = token.parse::<SocketAddr>() {
+        return Ok(Some(TargetSpec::Endpoints(vec![addr])));
+    }
+    Err(format!("Invalid target {:?}", token))
+}
+
+// masscan -oL 的一行：`open tcp 80 10.0.0.1 1390999524`
+fn parse_masscan_list<'a>(
+    status: &str,
+    mut columns: impl Iterator<Item = &'a str>,
+) -> Result<Option<TargetSpec>, String> {
+    let (Some(proto), Some(port), Some(ip)) = (columns.next(), columns.next(), columns.next()) else {
+        return Err("Truncated masscan list line".to_string());
+    };
+    if status != "open" || proto != "tcp" {
+        return Ok(None);
+    }
+    let port = port
+        .parse::<u16>()
+        .map_err(|e| format!("Invalid port {:?}: {}", port, e))?;
+    let ip = ip
+        .parse::<IpAddr>()
+        .map_err(|e| format!("Invalid IP {:?}: {}", ip, e))?;
+    Ok(Some(TargetSpec::Endpoints(vec![SocketAddr::new(ip, port)])))
+}
+
+// nmap -oG 的一行，去掉了开头的 `Host: `：
+// `10.0.0.1 ()\tPorts: 22/open/tcp//ssh///, 80/closed/tcp//http///\tIgnored State: filtered (998)`
+fn parse_nmap_grepable(host: &str) -> Result<TargetSpec, String> {
+    let mut fields = host.split('\t');
+    let ip = fields
+        .next()
+        .unwrap()
+        .split_whitespace()
+        .next()
+        .unwrap_or_default();
+    let ip = ip
+        .parse::<IpAddr>()
+        .map_err(|e| format!("Invalid IP {:?}: {}", ip, e))?;
+    let mut addrs = Vec::new();
+    if let Some(ports) = fields.find_map(|f| f.strip_prefix("Ports: ")) {
+        for port in ports.split(", ") {
+            let mut parts = port.split('/');
+            let (Some(port), Some(state), Some(proto)) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(format!("Invalid nmap port {:?}", port));
+            };
+            if state != "open" || proto != "tcp" {
+                continue;
+            }
+            let port = port
+                .parse::<u16>()
+                .map_err(|e| format!("Invalid port {:?}: {}", port, e))?;
+            addrs.push(SocketAddr::new(ip, port));
+        }
+    }
+    Ok(TargetSpec::Endpoints(addrs))
+}
+
+// 网段内的地址数，::/0 超出 u128 时取最大值
+fn net_size(net: &IpNet) -> u128 {
+    match net {
+        IpNet::V4(net) => 1 << (32 - net.prefix_len()),
+        IpNet::V6(net) if net.prefix_len() == 0 => u128::MAX,
+        IpNet::V6(net) => 1 << (128 - net.prefix_len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(nets: &[&str]) -> Targets {
+        targets_with_ports(nets, &[80])
+    }
+
+    fn targets_with_ports(nets: &[&str], ports: &[u16]) -> Targets {
+        let mut targets = Targets::new(ports);
+        for net in nets {
+            targets.add_net(net.parse().unwrap());
+        }
+        targets.finalize();
+        targets
+    }
+
+    #[test]
+    fn test_targets_count() {
+        assert_eq!(targets(&[]).count(), 0);
+        assert_eq!(targets(&["192.168.1.0/24"]).count(), 254);
+        assert_eq!(targets(&["192.168.1.1/32", "192.168.1.0/31"]).count(), 2);
+        assert_eq!(targets(&["2001:db8::/120", "2001:db8::1/128"]).count(), 256);
+        assert_eq!(targets(&["::/0", "10.0.0.1/32"]).count(), u128::MAX);
+    }
+
+    #[test]
+    fn test_targets_iter() {
+        let t = targets(&["2001:db8::/127", "10.0.0.0/30", "10.0.0.1/32"]);
+        let addrs: Vec<SocketAddr> = t.iter().collect();
+        let expected: Vec<SocketAddr> = [
+            "10.0.0.1:80",
+            "10.0.0.2:80",
+            "[2001:db8::]:80",
+            "[2001:db8::1]:80",
+        ]
+        .iter()
+        .map(|a| a.parse().unwrap())
+        .collect();
+        assert_eq!(addrs, expected);
+        assert_eq!(t.count(), addrs.len() as u128);
+    }
+
+    #[test]
+    fn test_targets_ports() {
+        let t = targets_with_ports(&["10.0.0.0/30"], &[22, 80, 443]);
+        assert_eq!(t.count(), 6);
+        let addrs: Vec<String> = t.iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            addrs,
+            [
+                "10.0.0.1:22",
+                "10.0.0.1:80",
+                "10.0.0.1:443",
+                "10.0.0.2:22",
+                "10.0.0.2:80",
+                "10.0.0.2:443",
+            ]
+        );
+        assert_eq!(targets_with_ports(&["::/0"], &[22, 80]).count(), u128::MAX);
+    }
+
+    #[test]
+    fn test_parse_target_line() {
+        let nets = |nets: &[&str]| {
+            Ok(Some(TargetSpec::Nets(
+                nets.iter().map(|n| n.parse().unwrap()).collect(),
+            )))
+        };
+
+        assert_eq!(parse_target_line(""), Ok(None));
+        assert_eq!(parse_target_line("   # comment"), Ok(None));
+        assert_eq!(
+            parse_target_line("173.245.48.0/20"),
+            nets(&["173.245.48.0/20"])
+        );
+        assert_eq!(
+            parse_target_line(" 2400:cb00::/32 # cloudflare"),
+            nets(&["2400:cb00::/32"])
+        );
+        assert_eq!(parse_target_line("10.0.0.1"), nets(&["10.0.0.1/32"]));
+        assert_eq!(parse_target_line("2001:db8::1"), nets(&["2001:db8::1/128"]));
+        assert_eq!(
+            parse_target_line("10.0.0.1-10.0.0.6"),
+            Ok(Some(TargetSpec::Ranges(
+                ["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/31", "10.0.0.6/32"]
+                    .iter()
+                    .map(|n| n.parse().unwrap())
+                    .collect()
+            )))
+        );
+        assert_eq!(
+            parse_target_line("10.0.0.1:8443\tmin: 1.0ms"),
+            Ok(Some(TargetSpec::Endpoints(vec!["10.0.0.1:8443"
+                .parse()
+                .unwrap()])))
+        );
+        assert_eq!(
+            parse_target_line("[2001:db8::1]:443"),
+            Ok(Some(TargetSpec::Endpoints(vec!["[2001:db8::1]:443"
+                .parse()
+                .unwrap()])))
+        );
+        assert!(parse_target_line("10.0.0.6-10.0.0.1").is_err());
+        assert!(parse_target_line("10.0.0.1-2001:db8::1").is_err());
+        assert!(parse_target_line("10.0.0.0/33").is_err());
+        assert!(parse_target_line("example.com").is_err());
+    }
+
+    #[test]
+    fn test_parse_scan_results() {
+        let endpoints = |addrs: &[&str]| {
+            Ok(Some(TargetSpec::Endpoints(
+                addrs.iter().map(|a| a.parse().unwrap()).collect(),
+            )))
+        };
+
+        assert_eq!(parse_target_line("#masscan"), Ok(None));
+        assert_eq!(
+            parse_target_line("open tcp 22 10.0.0.1 1700000000"),
+            endpoints(&["10.0.0.1:22"])
+        );
+        assert_eq!(
+            parse_target_line("open tcp 443 2001:db8::1 1700000000"),
+            endpoints(&["[2001:db8::1]:443"])
+        );
+        assert_eq!(
+            parse_target_line("closed tcp 23 10.0.0.1 1700000000"),
+            Ok(None)
+        );
+        assert_eq!(
+            parse_target_line("open udp 53 10.0.0.1 1700000000"),
+            Ok(None)
+        );
+        assert_eq!(
+            parse_target_line("banner tcp 22 10.0.0.1 1700000000 ssh SSH-2.0-OpenSSH_8.4"),
+            Ok(None)
+        );
+        assert!(parse_target_line("open tcp 22").is_err());
+        assert!(parse_target_line("open tcp 65536 10.0.0.1 1700000000").is_err());
+
+        assert_eq!(
+            parse_target_line("# Nmap 7.94 scan initiated as: nmap -oG - 10.0.0.1"),
+            Ok(None)
+        );
+        assert_eq!(
+            parse_target_line("Host: 10.0.0.1 ()\tStatus: Up"),
+            endpoints(&[])
+        );
+        assert_eq!(
+            parse_target_line(
+                "Host: 10.0.0.1 (gw.lan)\tPorts: 22/open/tcp//ssh//OpenSSH 8.4/, 23/closed/tcp//telnet///, 53/open/udp//domain///, 80/open/tcp//http///\tIgnored State: filtered (996)"
+            ),
+            endpoints(&["10.0.0.1:22", "10.0.0.1:80"])
+        );
+        assert!(parse_target_line("Host: gw.lan ()\tStatus: Up").is_err());
+    }
+
+    #[test]
+    fn test_targets_load() {
+        let input =
+            "# edge IPs\n\n10.0.0.0/30\n10.0.0.2\n10.0.0.1:443\n10.0.0.1:8443\n10.0.0.9:443\n";
+        let mut t = Targets::new(&[443]);
+        t.load(input.as_bytes(), "test").unwrap();
+        assert_eq!(t.finalize(), 0);
+        let addrs: Vec<String> = t.iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            addrs,
+            [
+                "10.0.0.1:443",
+                "10.0.0.2:443",
+                "10.0.0.1:8443",
+                "10.0.0.9:443"
+            ]
+        );
+        assert_eq!(t.count(), 4);
+
+        let err = Targets::new(&[443])
+            .load("10.0.0.1\nnot an ip\n".as_bytes(), "test")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("test:2: "));
+    }
+
+    #[test]
+    fn test_targets_load_aligned() {
+        // 合并后是对齐的网段，但不是以 CIDR 形式指定的，网络地址和广播地址也要扫描
+        let input = "10.0.0.4-10.0.0.7\n1.2.3.0\n1.2.3.1\n1.2.3.2\n1.2.3.3\n5.6.7.0-5.6.7.255\n";
+        let mut t = Targets::new(&[80]);
+        t.load(input.as_bytes(), "test").unwrap();
+        t.finalize();
+        assert_eq!(t.count(), 4 + 4 + 256);
+        let addrs: Vec<String> = t.iter().take(8).map(|a| a.to_string()).collect();
+        assert_eq!(
+            addrs,
+            [
+                "1.2.3.0:80",
+                "1.2.3.1:80",
+                "1.2.3.2:80",
+                "1.2.3.3:80",
+                "5.6.7.0:80",
+                "5.6.7.1:80",
+                "5.6.7.2:80",
+                "5.6.7.3:80",
+            ]
+        );
+        let addrs: Vec<String> = t.iter().skip(259).map(|a| a.to_string()).collect();
+        assert_eq!(
+            addrs,
+            [
+                "5.6.7.255:80",
+                "10.0.0.4:80",
+                "10.0.0.5:80",
+                "10.0.0.6:80",
+                "10.0.0.7:80"
+            ]
+        );
+
+        // 与 CIDR 重叠时，CIDR 的网络地址和广播地址只有单独指定了才扫描
+        let mut t = Targets::new(&[80]);
+        t.load("10.0.0.0/30\n10.0.0.3\n".as_bytes(), "test")
+            .unwrap();
+        t.finalize();
+        let addrs: Vec<String> = t.iter().map(|a| a.to_string()).collect();
+        assert_eq!(addrs, ["10.0.0.1:80", "10.0.0.2:80", "10.0.0.3:80"]);
+    }
+
+    #[test]
+    fn test_targets_exclude() {
+        let mut t = Targets::new(&[80]);
+        t.add_net("10.0.0.0/29".parse().unwrap());
+        t.add_net("2001:db8::/126".parse().unwrap());
+        t.add_endpoint("10.0.0.5:8080".parse().unwrap());
+        t.add_endpoint("10.0.0.6:8080".parse().unwrap());
+        t.exclude_net("10.0.0.4/31".parse().unwrap());
+        t.exclude_spec(TargetSpec::Endpoints(vec!["[2001:db8::2]:443"
+            .parse()
+            .unwrap()]));
+        assert_eq!(t.finalize(), 4);
+        let addrs: Vec<String> = t.iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            addrs,
+            [
+                "10.0.0.1:80",
+                "10.0.0.2:80",
+                "10.0.0.3:80",
+                "10.0.0.6:80",
+                "[2001:db8::]:80",
+                "[2001:db8::1]:80",
+                "[2001:db8::3]:80",
+                "10.0.0.6:8080",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_targets_exclude_reserved() {
+        let mut t = Targets::new(&[80]);
+        for net in [
+            "192.168.1.0/24",
+            "1.1.1.1/32",
+            "::1/128",
+            "2606:4700::1111/128",
+        ] {
+            t.add_net(net.parse().unwrap());
+        }
+        t.exclude_reserved();
+        assert_eq!(t.finalize(), 255);
+        let addrs: Vec<String> = t.iter().map(|a| a.to_string()).collect();
+        assert_eq!(addrs, ["1.1.1.1:80", "[2606:4700::1111]:80"]);
+    }
+
+    #[test]
+    fn test_targets_get() {
+        let mut t = Targets::new(&[22, 80]);
+        t.add_net("10.0.0.0/29".parse().unwrap());
+        t.add_net("2001:db8::/126".parse().unwrap());
+        t.add_endpoint("10.0.0.9:443".parse().unwrap());
+        t.exclude_net("10.0.0.4/32".parse().unwrap());
+        t.finalize();
+
+        let addrs: Vec<SocketAddr> = t.iter().collect();
+        assert_eq!(addrs.len() as u128, t.count());
+        for (i, addr) in addrs.iter().enumerate() {
+            assert_eq!(t.get(i as u64), *addr);
+        }
+
+        let mut shuffled: Vec<SocketAddr> = t.iter_random(1).unwrap().collect();
+        assert_ne!(shuffled, addrs);
+        shuffled.sort_unstable();
+        let mut sorted = addrs;
+        sorted.sort_unstable();
+        assert_eq!(shuffled, sorted);
+
+        assert!(targets(&["::/0"]).iter_random(1).is_none());
+    }
+}
@@ -0,0 +1,702 @@
+//! 不依赖 TLS 库的握手探测：构造 ClientHello，解析服务端返回的 ServerHello 和证书，不完成握手
+//!
+//! 格式见 RFC 5246 (TLS 1.2) 和 RFC 8446 (TLS 1.3)
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use x509_parser::extensions::GeneralName;
+use x509_parser::public_key::PublicKey;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+use crate::cyclic::SplitMix64;
+use crate::result::{Certificate, TlsInfo};
+
+pub const TLS12: u16 = 0x0303;
+pub const TLS13: u16 = 0x0304;
+
+// 记录层的内容类型
+const CONTENT_CHANGE_CIPHER_SPEC: u8 = 20;
+const CONTENT_ALERT: u8 = 21;
+const CONTENT_HANDSHAKE: u8 = 22;
+
+// 握手消息类型
+const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+const HANDSHAKE_SERVER_HELLO: u8 = 2;
+const HANDSHAKE_CERTIFICATE: u8 = 11;
+const HANDSHAKE_SERVER_HELLO_DONE: u8 = 14;
+
+// 扩展类型
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_SUPPORTED_GROUPS: u16 = 10;
+const EXT_EC_POINT_FORMATS: u16 = 11;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 13;
+const EXT_ALPN: u16 = 16;
+const EXT_EXTENDED_MASTER_SECRET: u16 = 23;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+const EXT_KEY_SHARE: u16 = 51;
+const EXT_RENEGOTIATION_INFO: u16 = 0xff01;
+
+const GROUP_X25519: u16 = 0x001d;
+
+// 提供的加密套件，TLS 1.3 的套件只在最高版本为 1.3 时提供
+const TLS13_CIPHERS: [(u16, &str); 3] = [
+    (0x1301, "TLS_AES_128_GCM_SHA256"),
+    (0x1302, "TLS_AES_256_GCM_SHA384"),
+    (0x1303, "TLS_CHACHA20_POLY1305_SHA256"),
+];
+const TLS12_CIPHERS: [(u16, &str); 15] = [
+    (0xc02b, "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256"),
+    (0xc02f, "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"),
+    (0xc02c, "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"),
+    (0xc030, "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"),
+    (0xcca9, "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256"),
+    (0xcca8, "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256"),
+    (0xc009, "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA"),
+    (0xc013, "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA"),
+    (0xc00a, "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA"),
+    (0xc014, "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA"),
+    (0x009c, "TLS_RSA_WITH_AES_128_GCM_SHA256"),
+    (0x009d, "TLS_RSA_WITH_AES_256_GCM_SHA384"),
+    (0x002f, "TLS_RSA_WITH_AES_128_CBC_SHA"),
+    (0x0035, "TLS_RSA_WITH_AES_256_CBC_SHA"),
+    (0x000a, "TLS_RSA_WITH_3DES_EDE_CBC_SHA"),
+];
+
+// ecdsa_secp256r1_sha256 等签名算法
+const SIGNATURE_ALGORITHMS: [u16; 11] = [
+    0x0403, 0x0804, 0x0401, 0x0503, 0x0805, 0x0501, 0x0806, 0x0601, 0x0807, 0x0201, 0x0203,
+];
+
+/// 构造 ClientHello 记录，`seed` 用于生成随机数、会话 ID 和 X25519 公钥
+///
+/// 公钥只是随机数，服务端可以完成 ServerHello，但之后的握手无法继续
+pub fn client_hello(sni: Option<&str>, alpn: &[String], max_version: u16, seed: u64) -> Vec<u8> {
+    let mut rng = SplitMix64(seed);
+    let mut random_bytes = |n: usize| -> Vec<u8> {
+        (0..(n + 7) / 8)
+            .flat_map(|_| rng.next().to_be_bytes())
+            .take(n)
+            .collect()
+    };
+    let tls13 = max_version >= TLS13;
+
+    let mut hello = Vec::with_capacity(512);
+    hello.extend(TLS12.to_be_bytes()); // TLS 1.3 也使用 1.2 的版本号，真正的版本在扩展中
+    hello.extend(random_bytes(32));
+    with_len8(&mut hello, |out| out.extend(random_bytes(32)));
+    with_len16(&mut hello, |out| {
+        let ciphers = if tls13 { &TLS13_CIPHERS[..] } else { &[] };
+        for (id, _) in ciphers.iter().chain(&TLS12_CIPHERS) {
+            out.extend(id.to_be_bytes());
+        }
+    });
+    hello.extend([1, 0]); // 只支持不压缩
+
+    with_len16(&mut hello, |out| {
+        if let Some(sni) = sni {
+            extension(out, EXT_SERVER_NAME, |out| {
+                with_len16(out, |out| {
+                    out.push(0); // host_name
+                    with_len16(out, |out| out.extend(sni.as_bytes()));
+                })
+            });
+        }
+        extension(out, EXT_SUPPORTED_GROUPS, |out| {
+            with_len16(out, |out| {
+                for group in [GROUP_X25519, 0x0017, 0x0018] {
+                    out.extend(group.to_be_bytes());
+                }
+            })
+        });
+        extension(out, EXT_EC_POINT_FORMATS, |out| {
+            with_len8(out, |out| out.push(0))
+        });
+        extension(out, EXT_SIGNATURE_ALGORITHMS, |out| {
+            with_len16(out, |out| {
+                for alg in SIGNATURE_ALGORITHMS {
+                    out.extend(alg.to_be_bytes());
+                }
+            })
+        });
+        if !alpn.is_empty() {
+            extension(out, EXT_ALPN, |out| {
+                with_len16(out, |out| {
+                    for protocol in alpn {
+                        with_len8(out, |out| out.extend(protocol.as_bytes()));
+                    }
+                })
+            });
+        }
+        extension(out, EXT_EXTENDED_MASTER_SECRET, |_| {});
+        extension(out, EXT_RENEGOTIATION_INFO, |out| out.push(0));
+        if tls13 {
+            extension(out, EXT_SUPPORTED_VERSIONS, |out| {
+                with_len8(out, |out| {
+                    for version in [TLS13, TLS12, 0x0302, 0x0301] {
+                        out.extend(version.to_be_bytes());
+                    }
+                })
+            });
+            extension(out, EXT_KEY_SHARE, |out| {
+                with_len16(out, |out| {
+                    out.extend(GROUP_X25519.to_be_bytes());
+                    with_len16(out, |out| out.extend(random_bytes(32)));
+                })
+            });
+        }
+    });
+
+    let mut handshake = vec![HANDSHAKE_CLIENT_HELLO];
+    handshake.extend(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend(hello);
+    // 记录层版本为 1.0，兼容只接受旧版本记录的服务端
+    let mut record = vec![CONTENT_HANDSHAKE, 0x03, 0x01];
+    record.extend((handshake.len() as u16).to_be_bytes());
+    record.extend(handshake);
+    record
+}
+
+// 写入一个扩展，长度在写完内容后填入
+fn extension(out: &mut Vec<u8>, ext_type: u16, f: impl FnOnce(&mut Vec<u8>)) {
+    out.extend(ext_type.to_be_bytes());
+    with_len16(out, f);
+}
+
+fn with_len8(out: &mut Vec<u8>, f: impl FnOnce(&mut Vec<u8>)) {
+    let pos = out.len();
+    out.push(0);
+    f(out);
+    out[pos] = (out.len() - pos - 1) as u8;
+}
+
+fn with_len16(out: &mut Vec<u8>, f: impl FnOnce(&mut Vec<u8>)) {
+    let pos = out.len();
+    out.extend([0, 0]);
+    f(out);
+    let len = (out.len() - pos - 2) as u16;
+    out[pos..pos + 2].copy_from_slice(&len.to_be_bytes());
+}
+
+/// 从服务端收到的数据中解析出的握手信息
+#[derive(Debug, PartialEq, Eq)]
+pub struct ServerFlight {
+    pub info: Option<TlsInfo>,
+    /// 已经得到全部可以得到的信息，或者服务端返回的不是 TLS，不需要再读取
+    pub complete: bool,
+}
+
+/// 解析服务端到目前为止返回的全部数据，最后一个不完整的记录或握手消息留到收到更多数据后再解析
+pub fn parse_server_flight(data: &[u8]) -> ServerFlight {
+    let mut handshake = Vec::new();
+    let mut record_version = None;
+    let mut alert = None;
+    let mut rest = data;
+    while rest.len() >= 5 {
+        let content_type = rest[0];
+        let version = u16::from_be_bytes([rest[1], rest[2]]);
+        let len = usize::from(u16::from_be_bytes([rest[3], rest[4]]));
+        if !(CONTENT_CHANGE_CIPHER_SPEC..=23).contains(&content_type) || rest[1] != 3 {
+            // 不是 TLS 记录
+            if record_version.is_none() {
+                return ServerFlight {
+                    info: None,
+                    complete: true,
+                };
+            }
+            break;
+        }
+        if rest.len() < 5 + len {
+            break;
+        }
+        let fragment = &rest[5..5 + len];
+        rest = &rest[5 + len..];
+        record_version.get_or_insert(version);
+        match content_type {
+            CONTENT_HANDSHAKE => handshake.extend_from_slice(fragment),
+            CONTENT_ALERT if fragment.len() >= 2 => {
+                alert = Some(alert_name(fragment[1]));
+                break;
+            }
+            CONTENT_CHANGE_CIPHER_SPEC => {}
+            // TLS 1.3 之后的内容都是加密的
+            _ => break,
+        }
+    }
+
+    let mut info: Option<TlsInfo> = None;
+    let mut complete = alert.is_some();
+    let mut messages = &handshake[..];
+    while messages.len() >= 4 {
+        let len = u32::from_be_bytes([0, messages[1], messages[2], messages[3]]) as usize;
+        if messages.len() < 4 + len {
+            break;
+        }
+        let body = &messages[4..4 + len];
+        match messages[0] {
+            HANDSHAKE_SERVER_HELLO => {
+                info = parse_server_hello(body);
+                // TLS 1.3 的证书是加密的，ServerHello 之后没有可以解析的内容
+                if info
+                    .as_ref()
+                    .map_or(true, |i| i.version == version_name(TLS13))
+                {
+                    complete = true;
+                }
+            }
+            HANDSHAKE_CERTIFICATE => {
+                if let Some(info) = info.as_mut() {
+                    info.certificate = parse_certificate_message(body);
+                }
+                complete = true;
+            }
+            HANDSHAKE_SERVER_HELLO_DONE => complete = true,
+            _ => {}
+        }
+        messages = &messages[4 + len..];
+    }
+
+    if let Some(alert) = alert {
+        let info = info.get_or_insert_with(|| TlsInfo {
+            version: version_name(record_version.unwrap_or(TLS12)),
+            ..TlsInfo::default()
+        });
+        info.alert = Some(alert);
+    }
+    ServerFlight { info, complete }
+}
+
+fn parse_server_hello(body: &[u8]) -> Option<TlsInfo> {
+    let mut r = Reader(body);
+    let mut version = r.u16()?;
+    r.bytes(32)?; // random
+    r.vec8()?; // session id
+    let cipher = r.u16()?;
+    r.u8()?; // compression
+    let mut alpn = None;
+    let mut extensions = Vec::new();
+    // 没有扩展时整个字段可以省略
+    let mut exts = Reader(r.vec16().unwrap_or_default());
+    while !exts.0.is_empty() {
+        let ext_type = exts.u16()?;
+        let mut data = Reader(exts.vec16()?);
+        match ext_type {
+            EXT_SUPPORTED_VERSIONS => version = data.u16()?,
+            EXT_ALPN => {
+                let mut list = Reader(data.vec16()?);
+                alpn = Some(String::from_utf8_lossy(list.vec8()?).into_owned());
+            }
+            _ => {}
+        }
+        extensions.push(extension_name(ext_type));
+    }
+    Some(TlsInfo {
+        version: version_name(version),
+        cipher: Some(cipher_name(cipher)),
+        alpn,
+        extensions,
+        certificate: None,
+        alert: None,
+    })
+}
+
+// TLS 1.2 的 Certificate 消息，只解析服务端自己的证书，不解析证书链
+fn parse_certificate_message(body: &[u8]) -> Option<Certificate> {
+    let mut r = Reader(body);
+    let mut list = Reader(r.vec24()?);
+    parse_certificate(list.vec24()?)
+}
+
+fn parse_certificate(der: &[u8]) -> Option<Certificate> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    let sans = match cert.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(format!("DNS:{dns}")),
+                GeneralName::IPAddress(ip) => match ip.len() {
+                    4 => Some(format!(
+                        "IP:{}",
+                        Ipv4Addr::from(<[u8; 4]>::try_from(*ip).ok()?)
+                    )),
+                    16 => Some(format!(
+                        "IP:{}",
+                        Ipv6Addr::from(<[u8; 16]>::try_from(*ip).ok()?)
+                    )),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    Some(Certificate {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        sans,
+        not_before: format_time(cert.validity().not_before.timestamp()),
+        not_after: format_time(cert.validity().not_after.timestamp()),
+        key: key_name(cert.public_key()),
+    })
+}
+
+// 公钥的算法和长度，例如 `RSA 2048` 或 `EC P-256`
+fn key_name(spki: &SubjectPublicKeyInfo) -> String {
+    let oid = |o: Option<String>| o.unwrap_or_default();
+    match spki.parsed() {
+        Ok(PublicKey::RSA(rsa)) => format!("RSA {}", rsa.key_size()),
+        Ok(PublicKey::EC(_)) => {
+            let curve = oid(spki
+                .algorithm
+                .parameters
+                .as_ref()
+                .and_then(|p| p.as_oid().ok())
+                .map(|o| o.to_id_string()));
+            let name = match curve.as_str() {
+                "1.2.840.10045.3.1.7" => "P-256",
+                "1.3.132.0.34" => "P-384",
+                "1.3.132.0.35" => "P-521",
+                other => other,
+            };
+            format!("EC {name}")
+        }
+        _ => match spki.algorithm.algorithm.to_id_string().as_str() {
+            "1.3.101.112" => "Ed25519".to_string(),
+            "1.3.101.113" => "Ed448".to_string(),
+            other => other.to_string(),
+        },
+    }
+}
+
+// Unix 时间转换成 RFC 3339 格式的 UTC 时间
+fn format_time(timestamp: i64) -> String {
+    let (days, secs) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    // 见 http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+pub fn version_name(version: u16) -> String {
+    match version {
+        0x0300 => "SSLv3".to_string(),
+        0x0301 => "TLSv1.0".to_string(),
+        0x0302 => "TLSv1.1".to_string(),
+        TLS12 => "TLSv1.2".to_string(),
+        TLS13 => "TLSv1.3".to_string(),
+        _ => format!("0x{version:04x}"),
+    }
+}
+
+fn cipher_name(cipher: u16) -> String {
+    TLS13_CIPHERS
+        .iter()
+        .chain(&TLS12_CIPHERS)
+        .find(|(id, _)| *id == cipher)
+        .map_or_else(|| format!("0x{cipher:04x}"), |(_, name)| name.to_string())
+}
+
+fn extension_name(ext_type: u16) -> String {
+    match ext_type {
+        EXT_SERVER_NAME => "server_name".to_string(),
+        5 => "status_request".to_string(),
+        EXT_SUPPORTED_GROUPS => "supported_groups".to_string(),
+        EXT_EC_POINT_FORMATS => "ec_point_formats".to_string(),
+        EXT_ALPN => "application_layer_protocol_negotiation".to_string(),
+        18 => "signed_certificate_timestamp".to_string(),
+        EXT_EXTENDED_MASTER_SECRET => "extended_master_secret".to_string(),
+        35 => "session_ticket".to_string(),
+        EXT_SUPPORTED_VERSIONS => "supported_versions".to_string(),
+        EXT_KEY_SHARE => "key_share".to_string(),
+        EXT_RENEGOTIATION_INFO => "renegotiation_info".to_string(),
+        _ => ext_type.to_string(),
+    }
+}
+
+fn alert_name(description: u8) -> String {
+    match description {
+        0 => "close_notify".to_string(),
+        10 => "unexpected_message".to_string(),
+        40 => "handshake_failure".to_string(),
+        47 => "illegal_parameter".to_string(),
+        50 => "decode_error".to_string(),
+        70 => "protocol_version".to_string(),
+        71 => "insufficient_security".to_string(),
+        80 => "internal_error".to_string(),
+        109 => "missing_extension".to_string(),
+        112 => "unrecognized_name".to_string(),
+        120 => "no_application_protocol".to_string(),
+        _ => description.to_string(),
+    }
+}
+
+// 按 TLS 的编码读取字段，数据不足时返回 None
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let b = self.bytes(2)?;
+        Some(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()?;
+        self.bytes(usize::from(len))
+    }
+
+    fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()?;
+        self.bytes(usize::from(len))
+    }
+
+    fn vec24(&mut self) -> Option<&'a [u8]> {
+        let b = self.bytes(3)?;
+        self.bytes(u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    }
+}
+
+// 测试用的 example.com 自签名证书，由下面的命令生成
+// openssl req -x509 -newkey ec -pkeyopt ec_paramgen_curve:P-256 -subj "/CN=example.com/O=Example"
+//   -addext "subjectAltName=DNS:example.com,DNS:www.example.com,IP:192.0.2.1"
+#[cfg(test)]
+pub(crate) const TEST_CERT: &str = "\
+    MIIBxDCCAWmgAwIBAgIBATAKBggqhkjOPQQDAjAoMRQwEgYDVQQDDAtleGFtcGxlLmNvbTEQMA4GA1UECgwHRXhhbXBsZTAeFw0y\
+    NDAxMDEwMDAwMDBaFw0yNTAxMDEwMDAwMDBaMCgxFDASBgNVBAMMC2V4YW1wbGUuY29tMRAwDgYDVQQKDAdFeGFtcGxlMFkwEwYH\
+    KoZIzj0CAQYIKoZIzj0DAQcDQgAEvbZmAyHvgwd9SYX4Uvr50p+Bp3E+GvnKJiY4njzKDBZElIkCGaRqIiSxm9WNXO2tdazHRA9s\
+    Pa+FA3kx9l43CqOBgzCBgDAdBgNVHQ4EFgQUrf10IfyidTX1nr8YNAOuux/z7F4wHwYDVR0jBBgwFoAUrf10IfyidTX1nr8YNAOu\
+    ux/z7F4wDwYDVR0TAQH/BAUwAwEB/zAtBgNVHREEJjAkggtleGFtcGxlLmNvbYIPd3d3LmV4YW1wbGUuY29thwTAAAIBMAoGCCqG\
+    SM49BAMCA0kAMEYCIQCMS9B85sKF5oXm2uwNQq9CjgoXhxfT8BI49dMClM3cYQIhAKpSVpxMo7QeEc7rI9fR4O2jaSrIDHE013vO\
+    RWTUpQE6";
+
+// 和 TEST_CERT 对应的 PKCS#8 私钥，用于在测试中运行 TLS 服务端
+#[cfg(test)]
+pub(crate) const TEST_KEY: &str = "\
+    MIGHAgEAMBMGByqGSM49AgEGCCqGSM49AwEHBG0wawIBAQQg2Tmn7ZSQiJN9qKQLkSycD4QaaBWOqkFUIMYYzdEUSt+hRANCAAS9\
+    tmYDIe+DB31JhfhS+vnSn4GncT4a+comJjiePMoMFkSUiQIZpGoiJLGb1Y1c7a11rMdED2w9r4UDeTH2XjcK";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use base64::Engine;
+
+    fn handshake(msg_type: u8, body: &[u8]) -> Vec<u8> {
+        let mut msg = vec![msg_type];
+        msg.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend(body);
+        msg
+    }
+
+    fn record(content_type: u8, fragment: &[u8]) -> Vec<u8> {
+        let mut record = vec![content_type, 3, 3];
+        record.extend((fragment.len() as u16).to_be_bytes());
+        record.extend(fragment);
+        record
+    }
+
+    fn server_hello(cipher: u16, extensions: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut body = TLS12.to_be_bytes().to_vec();
+        body.extend([0; 32]);
+        body.push(0);
+        body.extend(cipher.to_be_bytes());
+        body.push(0);
+        with_len16(&mut body, |out| {
+            for (ext_type, data) in extensions {
+                extension(out, *ext_type, |out| out.extend(*data));
+            }
+        });
+        handshake(HANDSHAKE_SERVER_HELLO, &body)
+    }
+
+    #[test]
+    fn test_client_hello() {
+        let alpn = ["h2".to_string(), "http/1.1".to_string()];
+        let hello = client_hello(Some("example.com"), &alpn, TLS13, 1);
+        assert_eq!(hello, client_hello(Some("example.com"), &alpn, TLS13, 1));
+        assert_ne!(hello, client_hello(Some("example.com"), &alpn, TLS13, 2));
+
+        let mut r = Reader(&hello);
+        assert_eq!(r.u8(), Some(CONTENT_HANDSHAKE));
+        assert_eq!(r.u16(), Some(0x0301));
+        let mut msg = Reader(r.vec16().unwrap());
+        assert!(r.0.is_empty());
+        assert_eq!(msg.u8(), Some(HANDSHAKE_CLIENT_HELLO));
+        let mut body = Reader(msg.vec24().unwrap());
+        assert_eq!(body.u16(), Some(TLS12));
+        body.bytes(32).unwrap();
+        assert_eq!(body.vec8().unwrap().len(), 32);
+        let ciphers = body.vec16().unwrap();
+        assert_eq!(
+            ciphers.len(),
+            2 * (TLS13_CIPHERS.len() + TLS12_CIPHERS.len())
+        );
+        assert_eq!(&ciphers[..2], [0x13, 0x01]);
+        assert_eq!(body.vec8(), Some(&[0][..]));
+        let mut exts = Reader(body.vec16().unwrap());
+        assert!(body.0.is_empty());
+        let mut found = Vec::new();
+        while !exts.0.is_empty() {
+            let ext_type = exts.u16().unwrap();
+            let data = exts.vec16().unwrap();
+            match ext_type {
+                EXT_SERVER_NAME => assert_eq!(data, b"\x00\x0e\x00\x00\x0bexample.com"),
+                EXT_ALPN => assert_eq!(data, b"\x00\x0c\x02h2\x08http/1.1"),
+                EXT_SUPPORTED_VERSIONS => assert_eq!(data, b"\x08\x03\x04\x03\x03\x03\x02\x03\x01"),
+                _ => {}
+            }
+            found.push(ext_type);
+        }
+        assert_eq!(
+            found,
+            [
+                EXT_SERVER_NAME,
+                EXT_SUPPORTED_GROUPS,
+                EXT_EC_POINT_FORMATS,
+                EXT_SIGNATURE_ALGORITHMS,
+                EXT_ALPN,
+                EXT_EXTENDED_MASTER_SECRET,
+                EXT_RENEGOTIATION_INFO,
+                EXT_SUPPORTED_VERSIONS,
+                EXT_KEY_SHARE
+            ]
+        );
+
+        // 最高版本为 1.2 时不提供 1.3 的套件和扩展，也没有 SNI 和 ALPN
+        let hello12 = client_hello(None, &[], TLS12, 1);
+        assert!(hello12.len() < hello.len());
+        assert!(!hello12.windows(2).any(|w| w == [0x13, 0x01]));
+    }
+
+    #[test]
+    fn test_parse_tls12_flight() {
+        let der = base64::engine::general_purpose::STANDARD
+            .decode(TEST_CERT)
+            .unwrap();
+        let mut messages = server_hello(
+            0xc02b,
+            &[
+                (EXT_RENEGOTIATION_INFO, b"\x00"),
+                (EXT_ALPN, b"\x00\x03\x02h2"),
+                (EXT_EXTENDED_MASTER_SECRET, b""),
+            ],
+        );
+        let mut certificates = Vec::new();
+        let mut cert = (der.len() as u32).to_be_bytes()[1..].to_vec();
+        cert.extend(&der);
+        certificates.extend(&(cert.len() as u32).to_be_bytes()[1..]);
+        certificates.extend(cert);
+        messages.extend(handshake(HANDSHAKE_CERTIFICATE, &certificates));
+        messages.extend(handshake(HANDSHAKE_SERVER_HELLO_DONE, b""));
+        // 证书消息跨越两个记录
+        let split = 300;
+        let mut data = record(CONTENT_HANDSHAKE, &messages[..split]);
+        data.extend(record(CONTENT_HANDSHAKE, &messages[split..]));
+
+        // 数据不完整时等待更多数据
+        let partial = parse_server_flight(&data[..5 + split]);
+        assert!(!partial.complete);
+        assert_eq!(
+            partial.info.unwrap().cipher.unwrap(),
+            "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256"
+        );
+        assert!(!parse_server_flight(&data[..data.len() - 1]).complete);
+
+        let flight = parse_server_flight(&data);
+        assert!(flight.complete);
+        assert_eq!(
+            flight.info.unwrap(),
+            TlsInfo {
+                version: "TLSv1.2".to_string(),
+                cipher: Some("TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256".to_string()),
+                alpn: Some("h2".to_string()),
+                extensions: vec![
+                    "renegotiation_info".to_string(),
+                    "application_layer_protocol_negotiation".to_string(),
+                    "extended_master_secret".to_string()
+                ],
+                certificate: Some(Certificate {
+                    subject: "CN=example.com, O=Example".to_string(),
+                    issuer: "CN=example.com, O=Example".to_string(),
+                    sans: vec![
+                        "DNS:example.com".to_string(),
+                        "DNS:www.example.com".to_string(),
+                        "IP:192.0.2.1".to_string()
+                    ],
+                    not_before: "2024-01-01T00:00:00Z".to_string(),
+                    not_after: "2025-01-01T00:00:00Z".to_string(),
+                    key: "EC P-256".to_string(),
+                }),
+                alert: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_tls13_alert_and_garbage() {
+        let hello = server_hello(
+            0x1301,
+            &[
+                (EXT_SUPPORTED_VERSIONS, b"\x03\x04"),
+                (EXT_KEY_SHARE, &[0; 36]),
+            ],
+        );
+        let mut data = record(CONTENT_HANDSHAKE, &hello);
+        data.extend(record(CONTENT_CHANGE_CIPHER_SPEC, b"\x01"));
+        data.extend(record(23, &[0xaa; 64]));
+        let flight = parse_server_flight(&data);
+        assert!(flight.complete);
+        let info = flight.info.unwrap();
+        assert_eq!(info.description(), "TLSv1.3 TLS_AES_128_GCM_SHA256");
+        assert_eq!(info.extensions, ["supported_versions", "key_share"]);
+
+        let alert = parse_server_flight(&record(CONTENT_ALERT, b"\x02\x28"));
+        assert!(alert.complete);
+        assert_eq!(
+            alert.info.unwrap().description(),
+            "TLSv1.2 alert handshake_failure"
+        );
+
+        assert_eq!(
+            parse_server_flight(b"HTTP/1.1 400 Bad Request\r\n"),
+            ServerFlight {
+                info: None,
+                complete: true
+            }
+        );
+        assert!(!parse_server_flight(b"\x16\x03").complete);
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_time(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_time(1_735_689_599), "2024-12-31T23:59:59Z");
+        assert_eq!(format_time(-1), "1969-12-31T23:59:59Z");
+    }
+}